@@ -1,3 +1,5 @@
+use std::io::IsTerminal;
+
 pub type Pos = (usize, usize); // (line, column)
 
 #[derive(PartialEq, Clone, Debug)]
@@ -13,10 +15,19 @@ pub enum Error {
     ErrorReadline(usize),
     ErrorEmptyFile,
     ErrorUnclosedString(Pos),
+    ErrorInterpolation(Pos),
     ErrorExpectedToken(Pos, String),
     ErrorUnxepectedToken(Loc, String, Pos),
     ErrorNullVar(Loc, String),
     ErrorNoParse(Pos, String),
+    ErrorWrongCode(Loc, String),
+    ErrorUnknownFn(Loc, String),
+    ErrorWrongArgs(Loc, String, usize),
+    ErrorReturnOutsideFn(Loc),
+    ErrorInclude(Loc, String),
+    ErrorIncludeCycle(Loc, String),
+    ErrorBodyFile(Loc, String),
+    ErrorPlainString(Loc, String),
     ErrorWrongExec,
     WarningEscapeSeq(Pos),
     WarningEmptyString(Pos),
@@ -26,7 +37,9 @@ pub enum Error {
 pub struct ErrorHandler {
     pub file: String,
     pub errors: Vec<Error>,
+    pub includes: Vec<ErrorHandler>,
     pub trigger: bool,
+    pub source: Vec<String>, // the lines read by the lexer, shown under the errors
 }
 
 impl ErrorHandler {
@@ -34,65 +47,258 @@ impl ErrorHandler {
         Self {
             file: file,
             errors: Vec::new(),
+            includes: Vec::new(),
             trigger: false,
+            source: Vec::new(),
         }
     }
 
-    pub fn display(&mut self) {
-        if self.errors.len() != 0 {
-            for e in self.errors.clone() {
-                match e {
-                    Error::ErrorWrongPath => {
-                        eprintln!("error: {}: No such file or directory", self.file)
-                    }
-                    Error::ErrorReadFile => eprintln!("error: {}: Can't read the file", self.file),
-                    Error::ErrorReadline(l) => {
-                        eprintln!("error: {}: Can't read the file at line {}", self.file, l)
-                    }
-                    Error::ErrorEmptyFile => {
-                        eprintln!("error: {}: No instructions were found", self.file)
-                    }
-                    Error::ErrorUnclosedString(p) => {
-                        eprintln!("error: {}:{}:{} Unclose string", self.file, p.0, p.1)
-                    }
-                    Error::ErrorExpectedToken(p, s) => {
-                        eprintln!("error: {}:{}:{} Expected token", self.file, p.0, p.1);
-                        println!("note: {} is expexted", s);
-                    }
-                    Error::ErrorUnxepectedToken(l, s, p) => {
-                        eprintln!(
-                            "error: {}:{}:{} Unexpected token",
-                            self.file, l.start.0, l.start.1
-                        );
-                        println!("note: {} is expexted at {}:{}:{}", s, self.file, p.0, p.1);
-                    }
-                    Error::ErrorNullVar(l, s) => eprintln!(
-                        "error: {}:{}:{} Variable `{}` has no value",
-                        self.file, l.start.0, l.start.1, s
-                    ),
-                    Error::ErrorNoParse(p, s) => eprintln!(
-                        "error: {}:{}:{} Impossible to parse at token `{}`",
-                        self.file, p.0, p.1, s
-                    ),
-                    Error::ErrorWrongExec => eprintln!(
-                        "error: {}: Something went wrong with the execution",
-                        self.file
-                    ),
-                    Error::WarningEscapeSeq(p) => eprintln!(
-                        "warning: {}:{}:{} Unknow escape sequence",
-                        self.file, p.0, p.1
-                    ),
-                    Error::WarningEmptyString(p) => {
-                        eprintln!("warning: {}:{}:{} Empty string", self.file, p.0, p.1)
-                    }
-                }
+    fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.includes.iter().all(|i| i.is_empty())
+    }
+
+    // (is an error, message, location, note)
+    fn describe(&self, e: &Error) -> (bool, String, Option<Loc>, Option<String>) {
+        let at = |p: &Pos| Loc {
+            start: *p,
+            end: (p.0, p.1 + 1),
+        };
+
+        match e {
+            Error::ErrorWrongPath => (true, String::from("No such file or directory"), None, None),
+            Error::ErrorReadFile => (true, String::from("Can't read the file"), None, None),
+            Error::ErrorReadline(l) => (
+                true,
+                format!("Can't read the file at line {}", l),
+                None,
+                None,
+            ),
+            Error::ErrorEmptyFile => (true, String::from("No instructions were found"), None, None),
+            Error::ErrorUnclosedString(p) => {
+                (true, String::from("Unclose string"), Some(at(p)), None)
             }
+            Error::ErrorInterpolation(p) => (
+                true,
+                String::from("Invalid interpolation"),
+                Some(at(p)),
+                Some(String::from("a variable name like ${name} is expected")),
+            ),
+            Error::ErrorExpectedToken(p, s) => (
+                true,
+                String::from("Expected token"),
+                Some(at(p)),
+                Some(format!("{} is expected", s)),
+            ),
+            Error::ErrorUnxepectedToken(l, s, p) => (
+                true,
+                String::from("Unexpected token"),
+                Some(l.clone()),
+                Some(format!(
+                    "{} is expected at {}:{}:{}",
+                    s, self.file, p.0, p.1
+                )),
+            ),
+            Error::ErrorNullVar(l, s) => (
+                true,
+                format!("Variable `{}` has no value", s),
+                Some(l.clone()),
+                None,
+            ),
+            Error::ErrorNoParse(p, s) => (
+                true,
+                format!("Impossible to parse at token `{}`", s),
+                Some(Loc {
+                    start: *p,
+                    end: (p.0, p.1 + s.chars().count()),
+                }),
+                None,
+            ),
+            Error::ErrorWrongCode(l, s) => (
+                true,
+                format!("Invalid status code `{}`", s),
+                Some(l.clone()),
+                Some(String::from(
+                    "a code like 200, 2xx, 200..299 or 200|204 is expected",
+                )),
+            ),
+            Error::ErrorUnknownFn(l, s) => (
+                true,
+                format!("Procedure `{}` is not defined", s),
+                Some(l.clone()),
+                Some(String::from(
+                    "procedures must be defined before they are called",
+                )),
+            ),
+            Error::ErrorWrongArgs(l, s, n) => (
+                true,
+                format!("Procedure `{}` takes {} argument(s)", s, n),
+                Some(l.clone()),
+                None,
+            ),
+            Error::ErrorReturnOutsideFn(l) => (
+                true,
+                String::from("Return outside of a procedure"),
+                Some(l.clone()),
+                None,
+            ),
+            Error::ErrorInclude(l, s) => (
+                true,
+                format!("Can't include `{}`: No such file or directory", s),
+                Some(l.clone()),
+                None,
+            ),
+            Error::ErrorIncludeCycle(l, s) => (
+                true,
+                format!("`{}` is already being included", s),
+                Some(l.clone()),
+                None,
+            ),
+            Error::ErrorBodyFile(l, s) => (
+                true,
+                format!("Can't read the body file `{}`", s),
+                Some(l.clone()),
+                Some(String::from(
+                    "the path is relative to the directory of the script",
+                )),
+            ),
+            Error::ErrorPlainString(l, s) => (
+                true,
+                format!("Variables can't be written inside {}", s),
+                Some(l.clone()),
+                Some(String::from("a plain string is expected, `\\$` is a dollar sign")),
+            ),
+            Error::ErrorWrongExec => (
+                true,
+                String::from("Something went wrong with the execution"),
+                None,
+                None,
+            ),
+            Error::WarningEscapeSeq(p) => (
+                false,
+                String::from("Unknow escape sequence"),
+                Some(at(p)),
+                None,
+            ),
+            Error::WarningEmptyString(p) => {
+                (false, String::from("Empty string"), Some(at(p)), None)
+            }
+        }
+    }
 
-            if self.trigger {
-                std::process::exit(1);
+    // Render an error like rustc does : the message, the source line and a caret under the location
+    pub fn render(&self, e: &Error, source: &[String], color: bool) -> String {
+        let (is_error, message, loc, note) = self.describe(e);
+        let paint = |code: &str, s: &str| {
+            if color {
+                format!("\x1b[{}m{}\x1b[0m", code, s)
             } else {
-                print!("\n");
+                String::from(s)
+            }
+        };
+        let level = if is_error { "1;31" } else { "1;33" };
+        let label = if is_error { "error" } else { "warning" };
+        let l = match loc {
+            Some(l) => l,
+            None => {
+                return paint(level, label)
+                    + &paint("1", format!(": {}: {}", self.file, message).as_str());
             }
+        };
+        let mut res = paint(level, label) + &paint("1", format!(": {}", message).as_str());
+
+        let number = l.start.0.to_string();
+        let pad = " ".repeat(number.len());
+        res.push_str(
+            format!(
+                "\n{}{} {}:{}:{}",
+                pad,
+                paint("1;34", "-->"),
+                self.file,
+                l.start.0,
+                l.start.1
+            )
+            .as_str(),
+        );
+
+        if let Some(line) = source.get(l.start.0.wrapping_sub(1)) {
+            // the columns are byte offsets like in the lexer, the widths are counted in characters
+            let col = l.start.1.saturating_sub(1);
+            let chars = |from: usize, to: usize| line.get(from..to).map(|s| s.chars().count());
+            let width = if l.end.0 == l.start.0 && l.end.1 > l.start.1 {
+                chars(col, l.end.1 - 1).unwrap_or(l.end.1 - l.start.1)
+            } else if l.end.0 > l.start.0 {
+                chars(col, line.len()).unwrap_or(1)
+            } else {
+                1
+            };
+            // keep the tabulations so the caret is aligned with the source
+            let offset: String = line
+                .get(..col)
+                .unwrap_or(line)
+                .chars()
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            res.push_str(format!("\n{} {}", pad, paint("1;34", "|")).as_str());
+            res.push_str(
+                format!(
+                    "\n{} {}",
+                    paint("1;34", number.as_str()),
+                    paint("1;34", "|")
+                )
+                .as_str(),
+            );
+            res.push_str(format!(" {}", line).as_str());
+            res.push_str(
+                format!(
+                    "\n{} {} {}{}",
+                    pad,
+                    paint("1;34", "|"),
+                    offset,
+                    paint(level, "^".repeat(width.max(1)).as_str())
+                )
+                .as_str(),
+            );
+        }
+
+        if let Some(n) = note {
+            res.push_str(
+                format!("\n{} {} {}", pad, paint("1;34", "="), paint("1", "note:")).as_str(),
+            );
+            res.push_str(format!(" {}", n).as_str());
+        }
+
+        res
+    }
+
+    fn print(&self, color: bool) {
+        for i in self.includes.iter() {
+            i.print(color);
+        }
+
+        for e in self.errors.iter() {
+            eprintln!("{}\n", self.render(e, &self.source, color));
+        }
+    }
+
+    // Print the errors and the warnings, true if there is an error
+    pub fn check(&self) -> bool {
+        if !self.is_empty() {
+            let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+            self.print(color);
+        }
+
+        self.trigger
+    }
+
+    // Keep the errors of an included file, they are displayed with its own name
+    pub fn include(&mut self, other: ErrorHandler) {
+        if other.trigger {
+            self.trigger = true;
+        }
+
+        if !other.is_empty() {
+            self.includes.push(other);
         }
     }
 
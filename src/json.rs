@@ -0,0 +1,168 @@
+use serde_json::Value;
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+// Parse a json path like `$.data.users[0]["first name"]`
+pub fn parse_path(path: &str) -> Option<Vec<PathSegment>> {
+    let chars: Vec<char> = path.trim().chars().collect();
+    let mut res = Vec::new();
+    let mut cursor = 0;
+
+    if chars.is_empty() || chars[0] != '$' {
+        return None;
+    }
+    cursor += 1;
+
+    while cursor < chars.len() {
+        if chars[cursor] == '.' {
+            cursor += 1;
+            let mut key = String::new();
+
+            while cursor < chars.len() && chars[cursor] != '.' && chars[cursor] != '[' {
+                key.push(chars[cursor]);
+                cursor += 1;
+            }
+
+            if key.is_empty() {
+                return None;
+            }
+            res.push(PathSegment::Key(key));
+        } else if chars[cursor] == '[' {
+            cursor += 1;
+
+            if cursor < chars.len() && (chars[cursor] == '"' || chars[cursor] == '\'') {
+                let seq = chars[cursor];
+                let mut key = String::new();
+                cursor += 1;
+
+                while cursor < chars.len() && chars[cursor] != seq {
+                    key.push(chars[cursor]);
+                    cursor += 1;
+                }
+
+                // skip the closing quote
+                cursor += 1;
+                res.push(PathSegment::Key(key));
+            } else {
+                let mut index = String::new();
+
+                while cursor < chars.len() && chars[cursor].is_ascii_digit() {
+                    index.push(chars[cursor]);
+                    cursor += 1;
+                }
+
+                match index.parse::<usize>() {
+                    Ok(i) => res.push(PathSegment::Index(i)),
+                    Err(_) => return None,
+                }
+            }
+
+            if cursor >= chars.len() || chars[cursor] != ']' {
+                return None;
+            }
+            cursor += 1;
+        } else {
+            return None;
+        }
+    }
+
+    Some(res)
+}
+
+pub fn select<'v>(value: &'v Value, path: &[PathSegment]) -> Option<&'v Value> {
+    let mut res = value;
+
+    for s in path {
+        res = match s {
+            PathSegment::Key(k) => res.get(k.as_str())?,
+            PathSegment::Index(i) => res.get(*i)?,
+        };
+    }
+
+    Some(res)
+}
+
+// Extract the value at `path` from a json document.
+// Strings are returned without their quotes, anything else as json.
+pub fn extract(document: &str, path: &str) -> Option<Value> {
+    let value: Value = serde_json::from_str(document).ok()?;
+    let path = parse_path(path)?;
+
+    select(&value, &path).cloned()
+}
+
+fn push_key(path: &str, key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        format!("{}.{}", path, key)
+    } else {
+        format!("{}[{:?}]", path, key)
+    }
+}
+
+// 3 and 3.0 are the same number, two integers are compared exactly
+pub fn same_number(a: &serde_json::Number, b: &serde_json::Number) -> bool {
+    if a.is_f64() || b.is_f64() {
+        a.as_f64() == b.as_f64()
+    } else {
+        a == b
+    }
+}
+
+fn same_value(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Number(e), Value::Number(a)) => same_number(e, a),
+        _ => expected == actual,
+    }
+}
+
+fn walk(expected: &Value, actual: &Value, subset: bool, path: String, res: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => {
+            for (k, v) in e {
+                match a.get(k) {
+                    Some(w) => walk(v, w, subset, push_key(&path, k), res),
+                    None => res.push(format!("{}: missing", push_key(&path, k))),
+                }
+            }
+
+            if !subset {
+                for k in a.keys() {
+                    if !e.contains_key(k) {
+                        res.push(format!("{}: unexpected", push_key(&path, k)));
+                    }
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(a)) => {
+            if e.len() != a.len() {
+                res.push(format!(
+                    "{}: expected {} elements, found {}",
+                    path,
+                    e.len(),
+                    a.len()
+                ));
+            } else {
+                for i in 0..e.len() {
+                    walk(&e[i], &a[i], subset, format!("{}[{}]", path, i), res);
+                }
+            }
+        }
+        _ => {
+            if !same_value(expected, actual) {
+                res.push(format!("{}: expected {}, found {}", path, expected, actual));
+            }
+        }
+    }
+}
+
+// Compare two json documents structurally and list the mismatching paths.
+// With `subset`, keys missing from `expected` are ignored in `actual`.
+pub fn diff(expected: &Value, actual: &Value, subset: bool) -> Vec<String> {
+    let mut res = Vec::new();
+    walk(expected, actual, subset, String::from("$"), &mut res);
+    res
+}
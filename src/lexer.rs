@@ -1,6 +1,7 @@
 use crate::error::Error::*;
 use crate::error::ErrorHandler;
 use crate::error::Loc;
+use crate::error::Pos;
 
 use std::fs::File;
 use std::io::BufRead;
@@ -13,16 +14,50 @@ pub enum TokenSort {
     TokenString,
     TokenNumber,
     TokenEq,
+    TokenEqEq,
+    TokenNeq,
     TokenPlus,
+    TokenConcat, // joins the parts of an interpolated string
+    TokenMinus,
+    TokenStar,
+    TokenSlash,
+    TokenPercent,
     TokenQmark,
+    TokenDotDot,
+    TokenPipe,
+    TokenLBrace,
+    TokenRBrace,
+    TokenLBracket,
+    TokenRBracket,
+    TokenComma,
+    TokenLParen,
+    TokenRParen,
     TokenPrint,
     TokenBody,
     TokenHeader,
     TokenJson,
+    TokenFrom,
+    TokenSubset,
+    TokenExpect,
+    TokenIf,
+    TokenElse,
+    TokenContains,
+    TokenFor,
+    TokenIn,
+    TokenFn,
+    TokenReturn,
+    TokenInclude,
+    TokenEnv,
+    TokenDollar,
     TokenGet,
     TokenPost,
     TokenPut,
     TokenDelete,
+    TokenPatch,
+    TokenHead,
+    TokenOptions,
+    TokenRequest,
+    TokenNewline,
 }
 
 pub struct Token {
@@ -66,6 +101,9 @@ impl<'l> Lexer<'l> {
                     } else {
                         self.cursor = 0;
                         self.line_nb = self.line_nb + 1;
+                        self.err
+                            .source
+                            .push(String::from(line.trim_end_matches(['\n', '\r'])));
                         self.line = line;
                     }
                 }
@@ -109,69 +147,186 @@ impl<'l> Lexer<'l> {
         res
     }
 
-    pub fn read_string(&mut self) -> Token {
+    // The characters of a string with their position, until the closing quote(s)
+    fn read_raw(&mut self, seq: char, triple: bool) -> Option<Vec<(char, Pos)>> {
+        let mut chars: Vec<(char, Pos)> = Vec::new();
+
+        while self.state {
+            if self.c == seq
+                && (!triple
+                    || (self.peek() == Some(seq)
+                        && self.line.as_bytes().get(self.cursor + 1) == Some(&(seq as u8))))
+            {
+                return Some(chars);
+            }
+
+            chars.push((self.c, (self.line_nb, self.cursor)));
+
+            if self.c == '\\' && self.cursor != self.line.len() {
+                self.advance();
+                chars.push((self.c, (self.line_nb, self.cursor)));
+            }
+            self.advance();
+        }
+
+        None
+    }
+
+    fn string_part(&mut self, buffer: &mut Vec<u8>, start: Pos, end: Pos) -> Token {
+        let content = String::from_utf8(std::mem::take(buffer)).unwrap_or_default();
+
+        Token {
+            sort: TokenSort::TokenString,
+            loc: Loc {
+                start,
+                end,
+            },
+            content,
+        }
+    }
+
+    // A string is one token, or with interpolations the tokens of `("..." + name + "...")`
+    pub fn read_string(&mut self) -> Vec<Token> {
         let start = (self.line_nb, self.cursor);
         let seq = self.c;
-        self.advance();
+        let triple = self.peek() == Some(seq)
+            && self.line.as_bytes().get(self.cursor + 1) == Some(&(seq as u8));
 
-        let content: String;
+        for _ in 0..(if triple { 3 } else { 1 }) {
+            self.advance();
+        }
+
+        let mut chars = match self.read_raw(seq, triple) {
+            Some(c) => {
+                for _ in 0..(if triple { 3 } else { 1 }) {
+                    self.advance();
+                }
+                c
+            }
+            None => {
+                self.err.push(ErrorUnclosedString(start));
+                Vec::new()
+            }
+        };
+        let end = (self.line_nb, self.cursor);
+
+        if triple {
+            chars = dedent(chars);
+        }
+
+        let mut parts: Vec<Token> = Vec::new();
+        let mut part_start = start;
         let mut buffer: Vec<u8> = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let (c, pos) = chars[i];
 
-        while (self.c != seq) && self.state {
-            if self.c == '\\' {
-                if self.cursor == self.line.len() {
-                    self.err
-                        .push_warning(WarningEscapeSeq((self.line_nb, self.cursor)));
+            if c == '\\' {
+                if i + 1 == chars.len() {
+                    self.err.push_warning(WarningEscapeSeq(pos));
                 } else {
-                    self.advance();
-                    if self.c == 'n' {
+                    i += 1;
+                    let (c, pos) = chars[i];
+
+                    if c == 'n' {
                         buffer.push('\n' as u8);
-                    } else if self.c == 't' {
+                    } else if c == 't' {
                         buffer.push('\t' as u8);
-                    } else if self.c == '\"' {
+                    } else if c == '\"' {
                         buffer.push('\"' as u8);
-                    } else if self.c == '\'' {
+                    } else if c == '\'' {
                         buffer.push('\'' as u8);
-                    } else if self.c == '\\' {
+                    } else if c == '\\' {
                         buffer.push('\\' as u8);
+                    } else if c == '$' {
+                        buffer.push(b'$');
                     } else {
-                        self.err
-                            .push_warning(WarningEscapeSeq((self.line_nb, self.cursor)));
-                        buffer.push(self.c as u8);
+                        self.err.push_warning(WarningEscapeSeq(pos));
+                        buffer.push(c as u8);
                     }
                 }
+            } else if c == '$' && chars.get(i + 1).map(|c| c.0) == Some('{') {
+                let part = self.string_part(&mut buffer, part_start, pos);
+                parts.push(part);
+                i += 2;
+
+                let mut name = String::new();
+                let name_start = chars.get(i).map(|c| c.1).unwrap_or(end);
+
+                while i < chars.len() && (chars[i].0.is_ascii_alphanumeric() || chars[i].0 == '_') {
+                    name.push(chars[i].0);
+                    i += 1;
+                }
+
+                if i == chars.len() || chars[i].0 != '}' || name.is_empty() {
+                    self.err.push(ErrorInterpolation(pos));
+                    part_start = chars.get(i).map(|c| c.1).unwrap_or(end);
+                    continue;
+                }
+
+                parts.push(Token {
+                    sort: TokenSort::TokenId,
+                    loc: Loc {
+                        start: name_start,
+                        end: chars[i].1,
+                    },
+                    content: name,
+                });
+                part_start = chars.get(i + 1).map(|c| c.1).unwrap_or(end);
             } else {
-                buffer.push(self.c as u8);
+                buffer.push(c as u8);
             }
 
-            self.advance();
+            i += 1;
         }
 
-        if !self.state || self.c != seq {
-            self.err.push(ErrorUnclosedString(start));
-        } else {
-            self.advance();
-        }
+        if parts.is_empty() {
+            let token = self.string_part(&mut buffer, start, end);
 
-        match String::from_utf8(buffer) {
-            Ok(r) => content = r,
-            _ => {
-                content = String::from("");
+            if token.content.as_str() == "" {
+                self.err.push_warning(WarningEmptyString(start));
             }
+            return vec![token];
         }
 
-        if content.as_str() == "" {
-            self.err.push_warning(WarningEmptyString(start));
-        }
+        let last = self.string_part(&mut buffer, part_start, end);
+        parts.push(last);
 
-        Token {
-            sort: TokenSort::TokenString,
+        let mut res = vec![Token {
+            sort: TokenSort::TokenLParen,
             loc: Loc {
                 start: start,
-                end: (self.line_nb, self.cursor),
+                end: (start.0, start.1 + 1),
             },
-            content: content,
+            content: String::from("("),
+        }];
+
+        for (i, part) in parts.into_iter().enumerate() {
+            // the first part is kept even if empty so that the result is always a string
+            if i > 0 && part.sort == TokenSort::TokenString && part.content.is_empty() {
+                continue;
+            }
+
+            if i > 0 {
+                res.push(Token {
+                    sort: TokenSort::TokenConcat,
+                    loc: part.loc.clone(),
+                    content: String::from("+"),
+                });
+            }
+            res.push(part);
         }
+
+        res.push(Token {
+            sort: TokenSort::TokenRParen,
+            loc: Loc {
+                start: (end.0, end.1.saturating_sub(1)),
+                end,
+            },
+            content: String::from(")"),
+        });
+        res
     }
 
     pub fn is_alpha(&mut self) -> bool {
@@ -197,6 +352,30 @@ impl<'l> Lexer<'l> {
             ts = TokenSort::TokenHeader;
         } else if content.as_str() == "json" {
             ts = TokenSort::TokenJson;
+        } else if content.as_str() == "from" {
+            ts = TokenSort::TokenFrom;
+        } else if content.as_str() == "subset" {
+            ts = TokenSort::TokenSubset;
+        } else if content.as_str() == "expect" {
+            ts = TokenSort::TokenExpect;
+        } else if content.as_str() == "if" {
+            ts = TokenSort::TokenIf;
+        } else if content.as_str() == "else" {
+            ts = TokenSort::TokenElse;
+        } else if content.as_str() == "contains" {
+            ts = TokenSort::TokenContains;
+        } else if content.as_str() == "for" {
+            ts = TokenSort::TokenFor;
+        } else if content.as_str() == "in" {
+            ts = TokenSort::TokenIn;
+        } else if content.as_str() == "fn" {
+            ts = TokenSort::TokenFn;
+        } else if content.as_str() == "return" {
+            ts = TokenSort::TokenReturn;
+        } else if content.as_str() == "include" || content.as_str() == "import" {
+            ts = TokenSort::TokenInclude;
+        } else if content.as_str() == "env" {
+            ts = TokenSort::TokenEnv;
         } else if content.as_str() == "get" {
             ts = TokenSort::TokenGet;
         } else if content.as_str() == "GET" {
@@ -213,6 +392,14 @@ impl<'l> Lexer<'l> {
             ts = TokenSort::TokenDelete;
         } else if content.as_str() == "DELETE" {
             ts = TokenSort::TokenDelete;
+        } else if content.as_str() == "patch" || content.as_str() == "PATCH" {
+            ts = TokenSort::TokenPatch;
+        } else if content.as_str() == "head" || content.as_str() == "HEAD" {
+            ts = TokenSort::TokenHead;
+        } else if content.as_str() == "options" || content.as_str() == "OPTIONS" {
+            ts = TokenSort::TokenOptions;
+        } else if content.as_str() == "request" {
+            ts = TokenSort::TokenRequest;
         } else {
             ts = TokenSort::TokenId;
         }
@@ -228,6 +415,31 @@ impl<'l> Lexer<'l> {
         res
     }
 
+    // Read an environment variable like $API_TOKEN
+    pub fn read_dollar(&mut self) -> Token {
+        let start = (self.line_nb, self.cursor);
+        let mut content = String::new();
+        self.advance();
+
+        while (self.c.is_ascii_alphanumeric() || self.c == '_') && self.state {
+            content.push(self.c);
+            self.advance();
+        }
+
+        if content.is_empty() {
+            self.err.push(ErrorNoParse(start, String::from("$")));
+        }
+
+        Token {
+            sort: TokenSort::TokenDollar,
+            loc: Loc {
+                start,
+                end: (self.line_nb, self.cursor),
+            },
+            content,
+        }
+    }
+
     pub fn is_digit(&mut self) -> bool {
         self.c.is_ascii_digit()
     }
@@ -236,8 +448,9 @@ impl<'l> Lexer<'l> {
         let start = (self.line_nb, self.cursor);
         let mut content = String::new();
 
-        while self.is_digit() && self.state {
-            content.push(self.c);
+        // a status code class like 2xx is read as a single number
+        while (self.is_digit() || self.c == 'x' || self.c == 'X') && self.state {
+            content.push(self.c.to_ascii_lowercase());
             self.advance();
         }
 
@@ -252,6 +465,27 @@ impl<'l> Lexer<'l> {
         res
     }
 
+    pub fn peek(&mut self) -> Option<char> {
+        self.line.as_bytes().get(self.cursor).map(|c| *c as char)
+    }
+
+    pub fn read_double(&mut self, ts: TokenSort) -> Token {
+        let start = (self.line_nb, self.cursor);
+        let mut content = String::from(self.c);
+        self.advance();
+        content.push(self.c);
+        self.advance();
+
+        Token {
+            sort: ts,
+            loc: Loc {
+                start,
+                end: (self.line_nb, self.cursor),
+            },
+            content,
+        }
+    }
+
     pub fn skip_space(&mut self) {
         while (self.c == ' ' || self.c == '\t') && self.state {
             self.advance();
@@ -263,6 +497,20 @@ impl<'l> Lexer<'l> {
         self.advance();
     }
 
+    // A line break ends a statement, blank lines and comments only give one token
+    pub fn push_newline(&mut self, res: &mut Vec<Token>) {
+        if res.last().is_some_and(|t| t.sort != TokenSort::TokenNewline) {
+            res.push(Token {
+                sort: TokenSort::TokenNewline,
+                loc: Loc {
+                    start: (self.line_nb, self.cursor),
+                    end: (self.line_nb, self.cursor + 1),
+                },
+                content: String::from("\\n"),
+            });
+        }
+    }
+
     pub fn get_tokens(&mut self) -> Vec<Token> {
         let mut res = Vec::new();
 
@@ -270,20 +518,54 @@ impl<'l> Lexer<'l> {
             self.skip_space();
 
             if self.c == '#' {
+                self.push_newline(&mut res);
                 self.skip_line();
+            } else if self.c == '=' && self.peek() == Some('=') {
+                res.push(self.read_double(TokenSort::TokenEqEq));
+            } else if self.c == '!' && self.peek() == Some('=') {
+                res.push(self.read_double(TokenSort::TokenNeq));
             } else if self.c == '=' {
                 res.push(self.read_char(TokenSort::TokenEq));
             } else if self.c == '+' {
                 res.push(self.read_char(TokenSort::TokenPlus));
+            } else if self.c == '-' {
+                res.push(self.read_char(TokenSort::TokenMinus));
+            } else if self.c == '*' {
+                res.push(self.read_char(TokenSort::TokenStar));
+            } else if self.c == '/' {
+                res.push(self.read_char(TokenSort::TokenSlash));
+            } else if self.c == '%' {
+                res.push(self.read_char(TokenSort::TokenPercent));
             } else if self.c == '?' {
                 res.push(self.read_char(TokenSort::TokenQmark));
+            } else if self.c == '|' {
+                res.push(self.read_char(TokenSort::TokenPipe));
+            } else if self.c == '.' && self.peek() == Some('.') {
+                res.push(self.read_double(TokenSort::TokenDotDot));
+            } else if self.c == '$' {
+                res.push(self.read_dollar());
+            } else if self.c == '{' {
+                res.push(self.read_char(TokenSort::TokenLBrace));
+            } else if self.c == '}' {
+                res.push(self.read_char(TokenSort::TokenRBrace));
+            } else if self.c == '[' {
+                res.push(self.read_char(TokenSort::TokenLBracket));
+            } else if self.c == ']' {
+                res.push(self.read_char(TokenSort::TokenRBracket));
+            } else if self.c == ',' {
+                res.push(self.read_char(TokenSort::TokenComma));
+            } else if self.c == '(' {
+                res.push(self.read_char(TokenSort::TokenLParen));
+            } else if self.c == ')' {
+                res.push(self.read_char(TokenSort::TokenRParen));
             } else if self.c == '\"' || self.c == '\'' {
-                res.push(self.read_string());
+                res.extend(self.read_string());
             } else if self.is_alpha() {
                 res.push(self.read_id());
             } else if self.is_digit() {
                 res.push(self.read_number());
             } else if self.c == '\n' {
+                self.push_newline(&mut res);
                 self.advance();
             } else {
                 self.advance()
@@ -298,6 +580,47 @@ impl<'l> Lexer<'l> {
     }
 }
 
+// Remove the line breaks after the opening and before the closing quotes of a
+// triple quoted string, and the indentation shared by all its lines
+fn dedent(chars: Vec<(char, Pos)>) -> Vec<(char, Pos)> {
+    let mut lines: Vec<Vec<(char, Pos)>> = vec![Vec::new()];
+
+    for c in chars {
+        if c.0 == '\n' {
+            lines.push(Vec::new());
+        } else if c.0 != '\r' {
+            lines.last_mut().unwrap().push(c);
+        }
+    }
+
+    let blank = |l: &Vec<(char, Pos)>| l.iter().all(|c| c.0 == ' ' || c.0 == '\t');
+
+    if lines.len() > 1 && blank(&lines[0]) {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && blank(&lines[lines.len() - 1]) {
+        lines.pop();
+    }
+
+    let indent = lines
+        .iter()
+        .filter(|l| !blank(l))
+        .map(|l| l.iter().take_while(|c| c.0 == ' ' || c.0 == '\t').count())
+        .min()
+        .unwrap_or(0);
+    let mut res = Vec::new();
+
+    for (i, line) in lines.into_iter().enumerate() {
+        if i > 0 {
+            let pos = line.first().map(|c| c.1).unwrap_or((0, 0));
+            res.push(('\n', pos));
+        }
+        res.extend(line.into_iter().skip(indent));
+    }
+
+    res
+}
+
 pub fn get_file_buf(file: &str, err: &mut ErrorHandler) -> Option<BufReader<File>> {
     if Path::new(&file).exists() {
         match File::open(file) {
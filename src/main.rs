@@ -1,7 +1,10 @@
 mod error;
+mod json;
 mod lexer;
 mod parser;
+mod report;
 mod test;
+mod value;
 mod vm;
 
 use std::env::args;
@@ -15,6 +18,17 @@ Option : -h, --help      Show this help.
          -f, --formated  Run script(s) and without any print, only a formated response (true or false).
          -s, --silent    Run script(s) with no route error print.
          -q, --quiet     Run script(s) without the print keyword.
+         -v, --verbose   Print every request and its response.
+         --truncate n    Print at most n characters of the bodies in verbose mode (default 1000, 0 for no limit).
+         -c, --check     Only check the script(s) for errors, without making any request.
+         --dump-bytecode Print the instructions of the compiled script(s) instead of running them.
+         --to-curl       Print a curl command for every request of the script(s) without making them.
+         -D, --var name=value
+                         Define a variable before running the script(s).
+         --format tap|json
+                         Print a TAP or JSON line for every request instead of the summary.
+         -r, --report junit=path
+                         Write a JUnit XML report of every request to path.
     ")
 }
 
@@ -34,6 +48,15 @@ fn main() {
         let mut formated = false;
         let mut silent = false;
         let mut quiet = false;
+        let mut defines: Vec<(String, String)> = Vec::new();
+        let mut junit: Option<String> = None;
+        let mut format = report::Format::Human;
+        let mut verbose = false;
+        let mut truncate: usize = 1000;
+        let mut to_curl = false;
+        let mut check = false;
+        let mut dump = false;
+        let mut failed = false; // a script couldn't be read, compiled or run
 
         while arg.len() > 0 && arg[0].starts_with('-') {
             if arg[0] == String::from("-b") || arg[0] == String::from("--blocking") {
@@ -50,6 +73,78 @@ fn main() {
             } else if arg[0] == String::from("-q") || arg[0] == String::from("--quiet") {
                 arg.remove(0);
                 quiet = true;
+            } else if arg[0] == "-v" || arg[0] == "--verbose" {
+                arg.remove(0);
+                verbose = true;
+            } else if arg[0] == "--truncate" {
+                arg.remove(0);
+
+                match arg.first().and_then(|a| a.parse::<usize>().ok()) {
+                    Some(n) => {
+                        truncate = n;
+                        arg.remove(0);
+                    }
+                    None => {
+                        eprintln!("error: --truncate : Expected a number of characters");
+                        usage();
+                        std::process::exit(1);
+                    }
+                }
+            } else if arg[0] == "-c" || arg[0] == "--check" {
+                arg.remove(0);
+                check = true;
+            } else if arg[0] == "--dump-bytecode" {
+                arg.remove(0);
+                dump = true;
+            } else if arg[0] == "--to-curl" {
+                arg.remove(0);
+                to_curl = true;
+                silent = true;
+                quiet = true;
+            } else if arg[0] == "-D" || arg[0] == "--var" {
+                arg.remove(0);
+
+                match arg.first().and_then(|a| a.split_once('=')) {
+                    Some((name, value)) => {
+                        defines.push((name.to_string(), value.to_string()));
+                        arg.remove(0);
+                    }
+                    None => {
+                        eprintln!("error: -D, --var : Expected a definition like name=value");
+                        usage();
+                        std::process::exit(1);
+                    }
+                }
+            } else if arg[0] == "--format" {
+                arg.remove(0);
+
+                match arg.first().map(|a| a.as_str()) {
+                    Some("tap") => format = report::Format::Tap,
+                    Some("json") => format = report::Format::Json,
+                    _ => {
+                        eprintln!("error: --format : Expected tap or json");
+                        usage();
+                        std::process::exit(1);
+                    }
+                }
+
+                arg.remove(0);
+                silent = true;
+                quiet = true;
+            } else if arg[0] == "-r" || arg[0] == "--report" {
+                arg.remove(0);
+
+                match arg.first().and_then(|a| a.split_once('=')) {
+                    Some(("junit", path)) => {
+                        junit = Some(path.to_string());
+                        arg.remove(0);
+                    }
+                    _ => {
+                        eprintln!("error: -r, --report : Expected a report like junit=path");
+                        usage();
+                        std::process::exit(1);
+                    }
+                }
             } else {
                 eprint!("error: {} : No such option\n", arg[0]);
                 usage();
@@ -63,60 +158,131 @@ fn main() {
             std::process::exit(1);
         }
 
-        let mut res: (usize, usize) = (0, 0); // (ok route, err route)
+        let mut res: (usize, usize) = (0, 0); // (ok route, err route) of every file
+        let many = arg.len() > 1;
+        let mut records: Vec<(String, Vec<vm::Record>)> = Vec::new();
+
+        if format == report::Format::Tap && !check {
+            println!("TAP version 13");
+        }
 
         for a in arg {
+            // the files after a failure aren't run in blocking mode
+            if blocking && !check && (res.1 != 0 || failed) {
+                break;
+            }
+
+            // a TAP stream bails out at the first script that can't be run
+            if format == report::Format::Tap && !check && failed {
+                break;
+            }
+
             let mut err = error::ErrorHandler::new(a.clone());
 
             match lexer::get_file_buf(a.as_str(), &mut err) {
                 Some(b) => {
                     let t = lexer::Lexer::new(b, &mut err).get_tokens();
-                    let inst = parser::Parser::new(t, &mut err).parse();
-                    err.display();
+                    let mut p = parser::Parser::new(t, &mut err);
 
-                    // if inst[0] == vm::Inst::InstPush(String::from("https://httpbin.org/anything")) {
-                    //     print!("1");
-                    // }
+                    for (name, _) in defines.iter() {
+                        p.define(name.clone());
+                    }
 
-                    // // if inst[1] == vm::Inst::InstPush(String::from("michel")) {
-                    // //     print!("2");
-                    // // }
+                    let inst = p.parse();
+                    let sources = p.sources();
 
-                    // if inst[1] == vm::Inst::InstPush(String::from("200")) {
-                    //     print!("3");
-                    // }
+                    // a script with errors isn't run, the next ones still are
+                    failed = err.check() || failed;
+                    if check || err.trigger {
+                        continue;
+                    }
 
-                    // if inst[2] == vm::Inst::InstReq(String::from("POST"), true) {
-                    //     print!("4");
-                    // }
+                    if dump {
+                        print!("{}:\n{}", a, vm::disassemble(&inst, &sources));
+                        continue;
+                    }
 
-                    match vm::Vm::new(inst, silent, quiet, blocking).execute() {
+                    let mut v = vm::Vm::new(a.clone(), inst, silent, quiet, blocking);
+                    v.sources(sources);
+
+                    for (name, value) in defines.iter() {
+                        v.define(name.clone(), value.clone());
+                    }
+
+                    if verbose {
+                        v.verbose(truncate);
+                    }
+
+                    if to_curl {
+                        v.dry_run();
+                    }
+
+                    v.stream(format, records.iter().map(|(_, r)| r.len()).sum());
+
+                    match v.execute() {
                         Some(r) => {
                             res.0 += r.0;
-                            res.1 += r.1
+                            res.1 += r.1;
+
+                            // each file has its own summary when there are several
+                            if many
+                                && format == report::Format::Human
+                                && !formated
+                                && r.0 + r.1 != 0
+                            {
+                                println!(
+                                    "{} : {} tests, {} successful, {} with errors",
+                                    a,
+                                    r.0 + r.1,
+                                    r.0,
+                                    r.1
+                                );
+                            }
                         }
                         None => {
                             err.push(error::Error::ErrorWrongExec);
-                            err.display();
-                        }
-                    }
-
-                    if formated {
-                        println!("{}", (res.1 == 0).to_string())
-                    } else {
-                        if res.0 + res.1 != 0 {
-                            if res.1 == 0 {
-                                println!("Done ! {} tests have been made with no errors.", res.0);
-                            } else {
-                                println!("Done ! {} tests have been made.", res.0 + res.1);
-                                println!("{} were successful", res.0);
-                                println!("{} came with errors", res.1);
-                            }
+                            failed = err.check() || failed;
                         }
                     }
+                    records.push((a.clone(), v.records.clone()));
                 }
-                None => err.display(),
+                None => failed = err.check() || failed,
             }
         }
+
+        if check {
+            std::process::exit(if failed { 1 } else { 0 });
+        }
+
+        if format == report::Format::Tap {
+            if failed {
+                println!("Bail out! A script can't be run");
+            } else {
+                println!("1..{}", records.iter().map(|(_, r)| r.len()).sum::<usize>());
+            }
+        }
+
+        if formated {
+            println!("{}", (res.1 == 0 && !failed).to_string())
+        } else if format == report::Format::Human && res.0 + res.1 != 0 {
+            if res.1 == 0 {
+                println!("Done ! {} tests have been made with no errors.", res.0);
+            } else {
+                println!("Done ! {} tests have been made.", res.0 + res.1);
+                println!("{} were successful", res.0);
+                println!("{} came with errors", res.1);
+            }
+        }
+
+        if let Some(path) = junit {
+            if std::fs::write(path.as_str(), report::junit(&records)).is_err() {
+                eprintln!("error: {}: Can't write the report", path);
+                std::process::exit(1);
+            }
+        }
+
+        if res.1 != 0 || failed {
+            std::process::exit(1);
+        }
     }
 }
@@ -2,14 +2,23 @@ use crate::error::Error::*;
 use crate::error::*;
 use crate::lexer::TokenSort::*;
 use crate::lexer::*;
+use crate::value::Value;
 use crate::vm::Inst::*;
 use crate::vm::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
 
 pub struct Parser<'l> {
     cursor: usize,
     tokens: Vec<Token>,
     program: Vec<Inst>,
     var_checker: Vec<String>,
+    functions: HashMap<String, (usize, usize)>, // name -> (address, parameters)
+    in_fn: bool,
+    includes: Vec<PathBuf>, // files being parsed, to detect include cycles
+    sources: Vec<(usize, String)>, // (first address, file) of the parts of the program
     err: &'l mut ErrorHandler,
 }
 
@@ -20,6 +29,10 @@ impl<'l> Parser<'l> {
             tokens: tokens,
             program: Vec::new(),
             var_checker: Vec::new(),
+            functions: HashMap::new(),
+            in_fn: false,
+            includes: fs::canonicalize(&err.file).into_iter().collect(),
+            sources: vec![(0, err.file.clone())],
             err: err,
         }
     }
@@ -36,12 +49,31 @@ impl<'l> Parser<'l> {
         self.tokens[self.cursor].content.clone()
     }
 
+    fn current_start(&self) -> Pos {
+        self.tokens[self.cursor].loc.start
+    }
+
+    // The location from `start` to the end of the last parsed token
+    fn loc_from(&self, start: Pos) -> Loc {
+        Loc {
+            start,
+            end: self.tokens[self.cursor - 1].loc.end,
+        }
+    }
+
     fn reach_end(&mut self) -> bool {
         self.cursor >= self.tokens.len()
     }
 
+    fn skip_newlines(&mut self) {
+        while !self.reach_end() && self.current_sort() == TokenNewline {
+            self.next();
+        }
+    }
+
     fn check_near_end(&mut self, s: &str) -> bool {
-        let res = self.cursor + 1 >= self.tokens.len();
+        let res = self.cursor + 1 >= self.tokens.len()
+            || self.tokens[self.cursor + 1].sort == TokenNewline;
 
         if res {
             self.add_err_exepected(s);
@@ -56,18 +88,19 @@ impl<'l> Parser<'l> {
     }
 
     fn add_inst_push(&mut self) {
-        self.program
-            .push(InstPush(self.tokens[self.cursor].content.clone()));
-    }
+        let content = self.tokens[self.cursor].content.clone();
 
-    fn add_inst_load(&mut self) {
-        self.program
-            .push(InstLoad(self.tokens[self.cursor].content.clone()));
+        match (self.current_sort(), Value::parse_number(content.as_str())) {
+            (TokenNumber, Some(n)) => self.program.push(InstPush(n)),
+            _ => self.program.push(InstPush(Value::String(content))),
+        }
     }
 
     fn add_inst_gain(&mut self) {
-        self.program
-            .push(InstGain(self.tokens[self.cursor].content.clone()));
+        self.program.push(InstGain(
+            self.tokens[self.cursor].content.clone(),
+            self.tokens[self.cursor].loc.clone(),
+        ));
     }
 
     fn add_err(&mut self, e: Error) {
@@ -75,6 +108,10 @@ impl<'l> Parser<'l> {
     }
 
     fn add_err_unexepected(&mut self, s: &str) {
+        if self.current_sort() == TokenNewline {
+            return self.add_err_exepected_after(s);
+        }
+
         self.err.push(ErrorUnxepectedToken(
             self.tokens[self.cursor].loc.clone(),
             String::from(s),
@@ -89,6 +126,13 @@ impl<'l> Parser<'l> {
         ));
     }
 
+    fn add_err_exepected_after(&mut self, s: &str) {
+        self.err.push(ErrorExpectedToken(
+            self.tokens[self.cursor - 1].loc.end,
+            String::from(s),
+        ));
+    }
+
     fn push_var(&mut self, var: String) {
         if !self.var_checker.contains(&var) {
             self.var_checker.push(var);
@@ -100,34 +144,116 @@ impl<'l> Parser<'l> {
         self.var_checker.contains(&s)
     }
 
-    pub fn parse_plus(&mut self) {
-        if !self.reach_end() {
-            if self.current_sort() == TokenPlus {
-                if self.check_near_end("a string or a variable") {
-                    self.next();
+    // value := product (("+" | "-") product)*
+    fn parse_value(&mut self) -> bool {
+        if self.reach_end() {
+            return false;
+        }
+        let start = self.current_start();
 
-                    if self.parse_value() {
-                        self.add_inst(InstPlus);
-                    }
-                }
+        if !self.parse_product() {
+            return false;
+        }
+
+        while !self.reach_end()
+            && (self.current_sort() == TokenPlus
+                || self.current_sort() == TokenConcat
+                || self.current_sort() == TokenMinus)
+        {
+            let sort = self.current_sort();
+
+            if !self.check_near_end("a value") {
+                return false;
+            }
+            self.next();
+
+            if !self.parse_product() {
+                return false;
+            }
+
+            match sort {
+                TokenPlus => self.add_inst(InstPlus(self.loc_from(start))),
+                TokenConcat => self.add_inst(InstConcat),
+                _ => self.add_inst(InstMinus(self.loc_from(start))),
             }
         }
+
+        true
     }
 
-    fn parse_value(&mut self) -> bool {
+    // product := unary (("*" | "/" | "%") unary)*
+    fn parse_product(&mut self) -> bool {
+        if self.reach_end() {
+            return false;
+        }
+        let start = self.current_start();
+
+        if !self.parse_unary() {
+            return false;
+        }
+
+        while !self.reach_end()
+            && matches!(self.current_sort(), TokenStar | TokenSlash | TokenPercent)
+        {
+            let sort = self.current_sort();
+
+            if !self.check_near_end("a value") {
+                return false;
+            }
+            self.next();
+
+            if !self.parse_unary() {
+                return false;
+            }
+
+            let loc = self.loc_from(start);
+            match sort {
+                TokenStar => self.add_inst(InstMul(loc)),
+                TokenSlash => self.add_inst(InstDiv(loc)),
+                _ => self.add_inst(InstMod(loc)),
+            }
+        }
+
+        true
+    }
+
+    // unary := "-" unary | primary
+    fn parse_unary(&mut self) -> bool {
+        if !self.reach_end() && self.current_sort() == TokenMinus {
+            let start = self.current_start();
+
+            if !self.check_near_end("a value") {
+                return false;
+            }
+            self.next();
+
+            if !self.parse_unary() {
+                return false;
+            }
+            self.add_inst(InstNeg(self.loc_from(start)));
+            return true;
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> bool {
         if !self.reach_end() {
             match self.current_sort() {
                 TokenString => {
                     self.add_inst_push();
                     self.next();
-                    self.parse_plus();
+                    return true;
+                }
+                TokenNumber if Value::parse_number(self.current_value().as_str()).is_some() => {
+                    self.add_inst_push();
+                    self.next();
                     return true;
                 }
                 TokenId => {
                     if self.check_var() {
                         self.add_inst_gain();
                         self.next();
-                        self.parse_plus();
                         return true;
                     } else {
                         self.add_err(ErrorNullVar(
@@ -137,6 +263,29 @@ impl<'l> Parser<'l> {
                         self.next();
                     }
                 }
+                TokenDollar => {
+                    self.add_inst_push();
+                    self.add_inst(InstEnv(self.tokens[self.cursor].loc.clone()));
+                    self.next();
+                    return true;
+                }
+                TokenEnv => return self.parse_env(),
+                TokenLParen => {
+                    if self.check_near_end("a value") {
+                        self.next();
+
+                        if self.parse_value() {
+                            if self.reach_end() {
+                                self.add_err_exepected_after("a closing parenthesis");
+                            } else if self.current_sort() != TokenRParen {
+                                self.add_err_unexepected("a closing parenthesis");
+                            } else {
+                                self.next();
+                                return true;
+                            }
+                        }
+                    }
+                }
                 _ => self.add_err_unexepected("a string or a variable"),
             }
         }
@@ -144,6 +293,42 @@ impl<'l> Parser<'l> {
         false
     }
 
+    fn parse_env(&mut self) -> bool {
+        let start = self.current_start();
+
+        if self.check_near_end("an opening parenthesis") {
+            self.next();
+
+            if self.current_sort() != TokenLParen {
+                self.add_err_unexepected("an opening parenthesis");
+                return false;
+            }
+
+            if self.check_near_end("a string or a variable") {
+                self.next();
+
+                if self.parse_value() {
+                    if self.reach_end() {
+                        self.add_err_exepected_after("a closing parenthesis");
+                    } else if self.current_sort() != TokenRParen {
+                        self.add_err_unexepected("a closing parenthesis");
+                    } else {
+                        self.next();
+                        self.add_inst(InstEnv(self.loc_from(start)));
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    // Declare a variable defined outside of the script
+    pub fn define(&mut self, var: String) {
+        self.push_var(var);
+    }
+
     fn parse_id(&mut self) {
         if self.check_near_end("a token") {
             let var = self.current_value();
@@ -157,12 +342,147 @@ impl<'l> Parser<'l> {
                         self.add_inst(InstLoad(var));
                     }
                 }
+            } else if self.current_sort() == TokenLParen {
+                self.parse_call(var, self.tokens[self.cursor - 1].loc.clone());
             } else {
                 self.add_err_unexepected("an equal sign")
             }
         }
     }
 
+    // Parse a comma separated list of items closed by `close`.
+    // `item` parses one item and tells if it was valid.
+    fn parse_args(&mut self, close: TokenSort, item: fn(&mut Self) -> bool) -> Option<usize> {
+        let mut len = 0;
+
+        // a list can be written on several lines
+        self.next();
+        self.skip_newlines();
+
+        while !self.reach_end() && self.current_sort() != close {
+            if !item(self) {
+                return None;
+            }
+            len += 1;
+            self.skip_newlines();
+
+            if self.reach_end() {
+                break;
+            }
+
+            if self.current_sort() == TokenComma {
+                self.next();
+                self.skip_newlines();
+            } else if self.current_sort() != close {
+                self.add_err_unexepected("a comma or a closing token");
+                return None;
+            }
+        }
+
+        if self.reach_end() {
+            self.add_err_exepected_after("a closing token");
+            return None;
+        }
+
+        self.next();
+        Some(len)
+    }
+
+    fn parse_call(&mut self, name: String, loc: Loc) {
+        if let Some(n) = self.parse_args(TokenRParen, Self::parse_value) {
+            match self.functions.get(&name) {
+                Some((addr, params)) => {
+                    if *params == n {
+                        self.add_inst(InstCall(*addr));
+                    } else {
+                        self.add_err(ErrorWrongArgs(loc, name, *params));
+                    }
+                }
+                None => self.add_err(ErrorUnknownFn(loc, name)),
+            }
+        }
+    }
+
+    fn parse_param(&mut self) -> bool {
+        if self.current_sort() == TokenId {
+            self.next();
+            true
+        } else {
+            self.add_err_unexepected("a parameter name");
+            false
+        }
+    }
+
+    fn parse_fn(&mut self) {
+        if self.check_near_end("a procedure name") {
+            self.next();
+
+            if self.current_sort() != TokenId {
+                self.add_err_unexepected("a procedure name");
+                return;
+            }
+            let name = self.current_value();
+
+            if !self.check_near_end("an opening parenthesis") {
+                return;
+            }
+            self.next();
+
+            if self.current_sort() != TokenLParen {
+                self.add_err_unexepected("an opening parenthesis");
+                return;
+            }
+
+            let first = self.cursor + 1;
+            let params: Vec<String> = match self.parse_args(TokenRParen, Self::parse_param) {
+                Some(_) => self.tokens[first..self.cursor - 1]
+                    .iter()
+                    .filter(|t| t.sort == TokenId)
+                    .map(|t| t.content.clone())
+                    .collect(),
+                None => return,
+            };
+
+            let jump = self.program.len();
+            self.add_inst(InstJump(0));
+            self.functions
+                .insert(name, (self.program.len(), params.len()));
+
+            // arguments are pushed in order, so the last one is on top of the stack
+            for p in params.iter().rev() {
+                self.add_inst(InstParam(p.clone()));
+            }
+
+            let globals = self.var_checker.clone();
+            let in_fn = self.in_fn;
+
+            for p in params.iter() {
+                self.push_var(p.clone());
+            }
+
+            self.in_fn = true;
+            self.parse_block();
+            self.in_fn = in_fn;
+            self.add_inst(InstRet);
+            self.program[jump] = InstJump(self.program.len());
+
+            for p in params.iter() {
+                if !globals.contains(p) {
+                    self.var_checker.retain(|v| v != p);
+                }
+            }
+        }
+    }
+
+    fn parse_return(&mut self) {
+        if !self.in_fn {
+            self.add_err(ErrorReturnOutsideFn(self.tokens[self.cursor].loc.clone()));
+        }
+
+        self.add_inst(InstRet);
+        self.next();
+    }
+
     fn parse_print(&mut self) {
         if self.check_near_end("a string or a variable") {
             self.next();
@@ -186,7 +506,167 @@ impl<'l> Parser<'l> {
         return 0;
     } 
 
-    pub fn parse_req(&mut self, method: &str) {
+    fn parse_compare_json(
+        &mut self,
+        method: &str,
+        body: bool,
+        json: bool,
+        headers: i32,
+        start: Pos,
+    ) {
+        if self.check_near_end("a string or a variable") {
+            let mut subset = false;
+            self.next();
+
+            if self.current_sort() == TokenSubset {
+                if !self.check_near_end("a string or a variable") {
+                    return;
+                }
+                subset = true;
+                self.next();
+            }
+
+            if self.parse_value() {
+                self.add_inst(InstReqandCompareJson(
+                    String::from(method),
+                    body,
+                    json,
+                    headers,
+                    subset,
+                    self.loc_from(start),
+                ));
+            }
+        }
+    }
+
+    fn check_code(code: &str) -> bool {
+        for alt in code.split('|') {
+            match alt.split_once("..") {
+                Some((low, high)) => match (low.parse::<u32>(), high.parse::<u32>()) {
+                    (Ok(l), Ok(h)) => {
+                        if l > h {
+                            return false;
+                        }
+                    }
+                    _ => return false,
+                },
+                None => {
+                    if alt.len() != 3 || alt.starts_with('x') {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    // Parse an expected status code like `200`, `2xx`, `200..299` or `200|201|204`
+    fn parse_code(&mut self) {
+        let start = self.tokens[self.cursor].loc.start;
+        let mut code = self.current_value();
+        self.next();
+
+        while !self.reach_end()
+            && (self.current_sort() == TokenPipe || self.current_sort() == TokenDotDot)
+        {
+            code.push_str(self.current_value().as_str());
+
+            if self.check_near_end("a status code") {
+                self.next();
+
+                if self.current_sort() == TokenNumber {
+                    code.push_str(self.current_value().as_str());
+                    self.next();
+                } else {
+                    self.add_err_unexepected("a status code");
+                    break;
+                }
+            }
+        }
+
+        if !Parser::check_code(code.as_str()) {
+            self.add_err(ErrorWrongCode(
+                Loc {
+                    start,
+                    end: self.tokens[self.cursor - 1].loc.end,
+                },
+                code.clone(),
+            ));
+        }
+
+        // a single code is a number, a class or a set of codes is a pattern
+        match Value::parse_number(code.as_str()) {
+            Some(n) => self.add_inst(InstPush(n)),
+            None => self.add_inst(InstPush(Value::String(code))),
+        }
+    }
+
+    // An interpolated string is read as `( "..." + name + "..." )`
+    fn is_interpolation(&self, at: usize) -> bool {
+        self.tokens.get(at).is_some_and(|t| t.sort == TokenLParen)
+            && self
+                .tokens
+                .get(at + 2)
+                .is_some_and(|t| t.sort == TokenConcat)
+    }
+
+    // Report an interpolated string as a whole where a plain string is required and skip it
+    fn skip_interpolation(&mut self, expected: &str) -> bool {
+        if !self.is_interpolation(self.cursor) {
+            return false;
+        }
+
+        let start = self.current_start();
+        while !self.reach_end() && self.current_sort() != TokenRParen {
+            self.next();
+        }
+        if !self.reach_end() {
+            self.next();
+        }
+        self.add_err(ErrorPlainString(self.loc_from(start), String::from(expected)));
+        true
+    }
+
+    // A body is a value, or the content of a file next to the script with `file "path"`
+    fn parse_body(&mut self) -> bool {
+        if self.current_sort() == TokenId
+            && self.current_value() == "file"
+            && self.is_interpolation(self.cursor + 1)
+        {
+            self.next();
+            self.skip_interpolation("a body file name");
+            return false;
+        }
+
+        if self.current_sort() == TokenId
+            && self.current_value() == "file"
+            && self
+                .tokens
+                .get(self.cursor + 1)
+                .is_some_and(|t| t.sort == TokenString)
+        {
+            let start = self.current_start();
+            self.next();
+            let name = self.current_value();
+            self.next();
+
+            match fs::read_to_string(self.resolve_path(name.as_str())) {
+                Ok(content) => {
+                    self.add_inst(InstPush(Value::String(content)));
+                    return true;
+                }
+                Err(_) => {
+                    self.add_err(ErrorBodyFile(self.loc_from(start), name));
+                    return false;
+                }
+            }
+        }
+
+        self.parse_value()
+    }
+
+    pub fn parse_req(&mut self, method: &str, start: Pos) {
         if self.check_near_end("a string or a variable") {
             let mut body: bool = false;
             let mut json: bool = false;
@@ -199,7 +679,7 @@ impl<'l> Parser<'l> {
                 if !self.reach_end() && self.current_sort() == TokenBody {
                     if self.check_near_end("a string or a variable") {
                         self.next();
-                        if self.parse_value() {
+                        if self.parse_body() {
                             body = true;
                         }
                     }
@@ -208,7 +688,7 @@ impl<'l> Parser<'l> {
                 if !self.reach_end() && self.current_sort() == TokenJson {
                     if self.check_near_end("a string or a variable") {
                         self.next();
-                        if self.parse_value() {
+                        if self.parse_body() {
                             body = true;
                             json = true;
                         }
@@ -217,10 +697,9 @@ impl<'l> Parser<'l> {
 
                 if !self.reach_end() {
                     if self.current_sort() == TokenNumber {
-                        self.add_inst_push();
-                        self.next();
+                        self.parse_code();
                     } else {
-                        self.add_inst(InstPush(String::from("200")));
+                        self.add_inst(InstPush(Value::Number(200.into())));
                     }
 
                     if !self.reach_end() {
@@ -231,15 +710,35 @@ impl<'l> Parser<'l> {
 
                                     if self.current_sort() == TokenId {
                                         let var = self.current_value();
-                                        self.add_inst(InstReqandPush(
-                                            String::from(method),
-                                            body,
-                                            json,
-                                            headers
-                                        ));
-                                        self.add_inst_load();
-                                        self.push_var(var);
                                         self.next();
+
+                                        if !self.reach_end() && self.current_sort() == TokenFrom {
+                                            if self.check_near_end("a json path") {
+                                                self.next();
+
+                                                if self.parse_value() {
+                                                    self.add_inst(InstReqandExtract(
+                                                        String::from(method),
+                                                        body,
+                                                        json,
+                                                        headers,
+                                                        self.loc_from(start),
+                                                    ));
+                                                    self.add_inst(InstLoad(var.clone()));
+                                                    self.push_var(var);
+                                                }
+                                            }
+                                        } else {
+                                            self.add_inst(InstReqandPush(
+                                                String::from(method),
+                                                body,
+                                                json,
+                                                headers,
+                                                self.loc_from(start),
+                                            ));
+                                            self.add_inst(InstLoad(var.clone()));
+                                            self.push_var(var);
+                                        }
                                     } else {
                                         self.add_err_unexepected("a variable name");
                                     }
@@ -249,57 +748,451 @@ impl<'l> Parser<'l> {
                                 if self.check_near_end("a string or a variable") {
                                     self.next();
 
-                                    if self.parse_value() {
+                                    if self.current_sort() == TokenJson {
+                                        self.parse_compare_json(method, body, json, headers, start);
+                                    } else if self.parse_value() {
                                         self.add_inst(InstReqandCompare(
                                             String::from(method),
                                             body,
                                             json,
-                                            headers
+                                            headers,
+                                            self.loc_from(start),
                                         ));
                                     }
                                 }
                             }
                             _ => {
-                                self.add_inst(InstReq(String::from(method), body, json, headers));
+                                self.add_inst(InstReq(
+                                    String::from(method),
+                                    body,
+                                    json,
+                                    headers,
+                                    self.loc_from(start),
+                                ));
                             }
                         }
                     } else {
-                        self.add_inst(InstReq(String::from(method), body, json, headers));
+                        self.add_inst(InstReq(
+                            String::from(method),
+                            body,
+                            json,
+                            headers,
+                            self.loc_from(start),
+                        ));
                     }
                 } else {
-                    self.add_inst(InstPush(String::from("200")));
-                    self.add_inst(InstReq(String::from(method), body, json, headers));
+                    self.add_inst(InstPush(Value::Number(200.into())));
+                    self.add_inst(InstReq(
+                        String::from(method),
+                        body,
+                        json,
+                        headers,
+                        self.loc_from(start),
+                    ));
                 }
             }
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Inst> {
-        while !self.reach_end() {
-            match self.current_sort() {
-                TokenId => self.parse_id(),
-                TokenPrint => self.parse_print(),
-                TokenGet => self.parse_req("GET"),
-                TokenPost => self.parse_req("POST"),
-                TokenPut => self.parse_req("PUT"),
-                TokenDelete => self.parse_req("DELETE"),
-                TokenString => {
-                    self.add_err(ErrorNoParse(
-                        self.tokens[self.cursor].loc.start,
-                        String::from("\"") + self.tokens[self.cursor].content.as_str() + "\"",
-                    ));
-                    self.next();
+    fn parse_expect(&mut self) {
+        let start = self.current_start();
+
+        if self.check_near_end("the keyword header") {
+            self.next();
+
+            if self.current_sort() != TokenHeader {
+                self.add_err_unexepected("the keyword header");
+                return;
+            }
+
+            if self.check_near_end("a string or a variable") {
+                self.next();
+
+                if self.parse_value() {
+                    if self.reach_end() {
+                        self.add_err_exepected_after("an equal sign or a question mark");
+                        return;
+                    }
+
+                    match self.current_sort() {
+                        TokenQmark => {
+                            if self.check_near_end("a string or a variable") {
+                                self.next();
+
+                                if self.parse_value() {
+                                    self.add_inst(InstExpectHeader(self.loc_from(start)));
+                                }
+                            }
+                        }
+                        TokenEq => {
+                            if self.check_near_end("a variable name") {
+                                self.next();
+
+                                if self.current_sort() == TokenId {
+                                    let var = self.current_value();
+                                    self.add_inst(InstHeader(self.loc_from(start)));
+                                    self.add_inst(InstLoad(var.clone()));
+                                    self.push_var(var);
+                                    self.next();
+                                } else {
+                                    self.add_err_unexepected("a variable name");
+                                }
+                            }
+                        }
+                        _ => self.add_err_unexepected("an equal sign or a question mark"),
+                    }
                 }
+            }
+        }
+    }
+
+    pub fn parse_custom_req(&mut self) {
+        let start = self.current_start();
+
+        if self.check_near_end("a method string") {
+            self.next();
+
+            if self.current_sort() == TokenString {
+                let method = self.current_value();
+                self.parse_req(method.as_str(), start);
+            } else if !self.skip_interpolation("a method string") {
+                self.add_err_unexepected("a method string");
+            }
+        }
+    }
+
+    fn parse_condition(&mut self) -> bool {
+        if self.parse_value() {
+            if self.reach_end() {
+                self.add_err_exepected_after("a comparison operator");
+                return false;
+            }
+
+            let inst = match self.current_sort() {
+                TokenEqEq => InstEq,
+                TokenNeq => InstNeq,
+                TokenContains => InstContains,
                 _ => {
-                    self.add_err(ErrorNoParse(
-                        self.tokens[self.cursor].loc.start,
-                        self.tokens[self.cursor].content.clone(),
-                    ));
-                    self.next();
+                    self.add_err_unexepected("a comparison operator");
+                    return false;
+                }
+            };
+
+            if self.check_near_end("a string or a variable") {
+                self.next();
+
+                if self.parse_value() {
+                    self.add_inst(inst);
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    fn parse_block(&mut self) {
+        self.skip_newlines();
+
+        if self.reach_end() {
+            self.add_err_exepected_after("an opening brace");
+        } else if self.current_sort() != TokenLBrace {
+            self.add_err_unexepected("an opening brace");
+        } else {
+            self.next();
+            self.parse_statements();
+
+            if self.reach_end() {
+                self.add_err_exepected_after("a closing brace");
+            } else {
+                self.next();
+            }
+        }
+    }
+
+    fn parse_if(&mut self) {
+        if self.check_near_end("a condition") {
+            self.next();
+
+            if self.parse_condition() {
+                let jump = self.program.len();
+                self.add_inst(InstJumpIfNot(0));
+                self.parse_block();
+
+                // `else` may start the line after the closing brace
+                let save = self.cursor;
+                self.skip_newlines();
+
+                if !self.reach_end() && self.current_sort() == TokenElse {
+                    let jump_else = self.program.len();
+                    self.add_inst(InstJump(0));
+                    self.program[jump] = InstJumpIfNot(self.program.len());
+
+                    if self.check_near_end("an opening brace") {
+                        self.next();
+
+                        if self.current_sort() == TokenIf {
+                            self.parse_if();
+                        } else {
+                            self.parse_block();
+                        }
+                    }
+
+                    self.program[jump_else] = InstJump(self.program.len());
+                } else {
+                    self.cursor = save;
+                    self.program[jump] = InstJumpIfNot(self.program.len());
                 }
             }
         }
+    }
+
+    fn parse_for(&mut self) {
+        let start = self.current_start();
 
+        if self.check_near_end("a variable name") {
+            self.next();
+
+            if self.current_sort() != TokenId {
+                self.add_err_unexepected("a variable name");
+                return;
+            }
+            let var = self.current_value();
+
+            if !self.check_near_end("the keyword in") {
+                return;
+            }
+            self.next();
+
+            if self.current_sort() != TokenIn {
+                self.add_err_unexepected("the keyword in");
+                return;
+            }
+
+            if !self.check_near_end("a list or a range") {
+                return;
+            }
+            self.next();
+
+            if self.current_sort() == TokenLBracket {
+                match self.parse_args(TokenRBracket, Self::parse_value) {
+                    Some(len) => self.add_inst(InstIter(len)),
+                    None => return,
+                }
+            } else {
+                if !self.parse_value() {
+                    return;
+                }
+
+                if self.reach_end() {
+                    self.add_err_exepected_after("two dots");
+                    return;
+                }
+
+                if self.current_sort() != TokenDotDot {
+                    self.add_err_unexepected("two dots");
+                    return;
+                }
+
+                if !self.check_near_end("a number or a variable") {
+                    return;
+                }
+                self.next();
+
+                if !self.parse_value() {
+                    return;
+                }
+                self.add_inst(InstIterRange(self.loc_from(start)));
+            }
+
+            let start = self.program.len();
+            self.add_inst(InstNext(var.clone(), 0));
+            self.push_var(var.clone());
+            self.parse_block();
+            self.add_inst(InstJump(start));
+            self.program[start] = InstNext(var, self.program.len());
+        }
+    }
+
+    // Resolve a path relative to the directory of the file being parsed
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        match Path::new(&self.err.file).parent() {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    fn parse_include(&mut self) {
+        if self.check_near_end("a file name") {
+            self.next();
+
+            if self.current_sort() != TokenString {
+                if !self.skip_interpolation("a file name") {
+                    self.add_err_unexepected("a file name");
+                }
+                return;
+            }
+
+            let name = self.current_value();
+            let loc = self.tokens[self.cursor].loc.clone();
+            let path = self.resolve_path(name.as_str());
+            self.next();
+
+            let canonical = match fs::canonicalize(&path) {
+                Ok(p) => p,
+                Err(_) => {
+                    self.add_err(ErrorInclude(loc, name));
+                    return;
+                }
+            };
+
+            if self.includes.contains(&canonical) {
+                self.add_err(ErrorIncludeCycle(loc, name));
+                return;
+            }
+
+            let mut err = ErrorHandler::new(path.to_string_lossy().to_string());
+            let file = err.file.clone();
+
+            if let Some(b) = get_file_buf(file.as_str(), &mut err) {
+                let tokens = Lexer::new(b, &mut err).get_tokens();
+                let mut parser = Parser::new(tokens, &mut err);
+
+                // the included file is parsed straight into this program
+                parser.program = std::mem::take(&mut self.program);
+                parser.var_checker = std::mem::take(&mut self.var_checker);
+                parser.functions = std::mem::take(&mut self.functions);
+                parser.includes = std::mem::take(&mut self.includes);
+                parser.includes.push(canonical);
+                parser.sources = std::mem::take(&mut self.sources);
+                parser.sources.push((parser.program.len(), file));
+                parser.parse_program();
+
+                self.program = std::mem::take(&mut parser.program);
+                self.var_checker = std::mem::take(&mut parser.var_checker);
+                self.functions = std::mem::take(&mut parser.functions);
+                self.includes = std::mem::take(&mut parser.includes);
+                self.includes.pop();
+                self.sources = std::mem::take(&mut parser.sources);
+                self.sources.push((self.program.len(), self.err.file.clone()));
+            }
+
+            self.err.include(err);
+        }
+    }
+
+    fn parse_statement(&mut self) {
+        match self.current_sort() {
+            TokenId => self.parse_id(),
+            TokenPrint => self.parse_print(),
+            TokenGet => self.parse_req("GET", self.current_start()),
+            TokenPost => self.parse_req("POST", self.current_start()),
+            TokenPut => self.parse_req("PUT", self.current_start()),
+            TokenDelete => self.parse_req("DELETE", self.current_start()),
+            TokenPatch => self.parse_req("PATCH", self.current_start()),
+            TokenHead => self.parse_req("HEAD", self.current_start()),
+            TokenOptions => self.parse_req("OPTIONS", self.current_start()),
+            TokenRequest => self.parse_custom_req(),
+            TokenExpect => self.parse_expect(),
+            TokenIf => self.parse_if(),
+            TokenFor => self.parse_for(),
+            TokenFn => self.parse_fn(),
+            TokenReturn => self.parse_return(),
+            TokenInclude => self.parse_include(),
+            TokenString => {
+                self.add_err(ErrorNoParse(
+                    self.tokens[self.cursor].loc.start,
+                    String::from("\"") + self.tokens[self.cursor].content.as_str() + "\"",
+                ));
+                self.next();
+            }
+            _ => {
+                self.add_err(ErrorNoParse(
+                    self.tokens[self.cursor].loc.start,
+                    self.tokens[self.cursor].content.clone(),
+                ));
+                self.next();
+            }
+        }
+    }
+
+    fn is_statement_start(&mut self) -> bool {
+        matches!(
+            self.current_sort(),
+            TokenPrint
+                | TokenGet
+                | TokenPost
+                | TokenPut
+                | TokenDelete
+                | TokenPatch
+                | TokenHead
+                | TokenOptions
+                | TokenRequest
+                | TokenExpect
+                | TokenIf
+                | TokenFor
+                | TokenFn
+                | TokenReturn
+                | TokenInclude
+        )
+    }
+
+    // Skip the rest of a wrong statement, with the blocks it opened,
+    // so that one mistake gives one error
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+
+        while !self.reach_end() {
+            match self.current_sort() {
+                TokenLBrace => depth += 1,
+                TokenRBrace if depth == 0 => return,
+                TokenRBrace => depth -= 1,
+                TokenNewline if depth == 0 => return,
+                _ if depth == 0 && self.is_statement_start() => return,
+                _ => {}
+            }
+            self.next();
+        }
+    }
+
+    // Parse statements until the end of the file or of the current block
+    fn parse_statements(&mut self) {
+        while !self.reach_end() && self.current_sort() != TokenRBrace {
+            if self.current_sort() == TokenNewline {
+                self.next();
+                continue;
+            }
+
+            let errors = self.err.errors.len();
+            self.parse_statement();
+
+            if self.err.errors.len() > errors {
+                self.synchronize();
+            }
+        }
+    }
+
+    fn parse_program(&mut self) {
+        while !self.reach_end() {
+            self.parse_statements();
+
+            if !self.reach_end() {
+                self.add_err(ErrorNoParse(
+                    self.tokens[self.cursor].loc.start,
+                    self.tokens[self.cursor].content.clone(),
+                ));
+                self.next();
+            }
+        }
+    }
+
+    // The files the instructions come from : from each address on, until the next one
+    pub fn sources(&self) -> Vec<(usize, String)> {
+        self.sources.clone()
+    }
+
+    pub fn parse(&mut self) -> Vec<Inst> {
+        self.parse_program();
         self.program.to_owned()
     }
 }
@@ -0,0 +1,185 @@
+use crate::vm::Record;
+use serde_json::json;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Format {
+    Human,
+    Tap,
+    Json,
+}
+
+fn escape(s: &str) -> String {
+    let mut res = String::new();
+
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            _ => res.push(c),
+        }
+    }
+
+    res
+}
+
+// Quote a shell argument
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// The curl command line making the same request
+pub fn curl(method: &str, url: &str, body: &str, json: bool, headers: &[String]) -> String {
+    let mut res = String::from("curl");
+
+    match method {
+        "GET" => {}
+        "HEAD" => res.push_str(" --head"),
+        _ => res.push_str(format!(" -X {}", method).as_str()),
+    }
+    res.push_str(format!(" {}", quote(url)).as_str());
+
+    if json {
+        res.push_str(" -H 'content-type: application/json'");
+    }
+
+    for h in headers {
+        res.push_str(format!(" -H {}", quote(h)).as_str());
+    }
+
+    if !body.is_empty() {
+        res.push_str(format!(" --data-raw {}", quote(body)).as_str());
+    }
+
+    res
+}
+
+// Build a JUnit XML report with one test suite per script file
+pub fn junit(results: &[(String, Vec<Record>)]) -> String {
+    let tests: usize = results.iter().map(|(_, r)| r.len()).sum();
+    let failures: usize = results
+        .iter()
+        .map(|(_, r)| r.iter().filter(|r| r.reason.is_some()).count())
+        .sum();
+    let mut res = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+
+    res.push_str(
+        format!(
+            "<testsuites name=\"goudron\" tests=\"{}\" failures=\"{}\">\n",
+            tests, failures
+        )
+        .as_str(),
+    );
+
+    for (file, records) in results {
+        res.push_str(
+            format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">\n",
+                escape(file),
+                records.len(),
+                records.iter().filter(|r| r.reason.is_some()).count()
+            )
+            .as_str(),
+        );
+
+        for r in records {
+            res.push_str(
+                format!(
+                    "    <testcase classname=\"{}\" name=\"{} {}\" file=\"{}\" line=\"{}\" time=\"{:.3}\">\n",
+                    escape(file),
+                    escape(&r.method),
+                    escape(&r.url),
+                    escape(&r.file),
+                    r.loc.start.0,
+                    r.duration.as_secs_f64()
+                )
+                .as_str(),
+            );
+
+            if let Some(reason) = &r.reason {
+                // header assertions are recorded with the values of the header
+                let what = if r.method == "header" { "value" } else { "code" };
+                let mut text = format!(
+                    "expected {}: {}\nactual {}: {}",
+                    what,
+                    r.expected,
+                    what,
+                    r.code.clone().unwrap_or(String::from("none"))
+                );
+
+                for d in r.details.iter() {
+                    text.push('\n');
+                    text.push_str(d);
+                }
+
+                res.push_str(
+                    format!(
+                        "      <failure message=\"{}\">{}</failure>\n",
+                        escape(reason),
+                        escape(&text)
+                    )
+                    .as_str(),
+                );
+            }
+
+            res.push_str("    </testcase>\n");
+        }
+
+        res.push_str("  </testsuite>\n");
+    }
+
+    res.push_str("</testsuites>\n");
+    res
+}
+
+// A TAP test line, with a YAML block describing the failure if any
+pub fn tap(n: usize, file: &str, r: &Record) -> String {
+    match &r.reason {
+        None => format!("ok {} - {}: {} {}", n, file, r.method, r.url),
+        Some(reason) => {
+            let mut res = format!(
+                "not ok {} - {}: {} {}\n  ---\n  message: {}\n  at: {}\n  expected: {}\n  found: {}\n  duration_ms: {}",
+                n,
+                file,
+                r.method,
+                r.url,
+                json!(reason),
+                json!(format!("{}:{}:{}", r.file, r.loc.start.0, r.loc.start.1)),
+                json!(r.expected),
+                json!(r.code),
+                r.duration.as_millis()
+            );
+
+            if !r.details.is_empty() {
+                res.push_str("\n  details:");
+
+                for d in r.details.iter() {
+                    res.push_str(format!("\n    - {}", json!(d)).as_str());
+                }
+            }
+
+            res.push_str("\n  ...");
+            res
+        }
+    }
+}
+
+// A JSON object on a single line describing a request
+pub fn json(r: &Record) -> String {
+    json!({
+        "file": r.file,
+        "line": r.loc.start.0,
+        "column": r.loc.start.1,
+        "method": r.method,
+        "url": r.url,
+        "expected": r.expected,
+        "code": r.code,
+        "duration_ms": r.duration.as_millis() as u64,
+        "ok": r.reason.is_none(),
+        "reason": r.reason,
+        "details": r.details,
+    })
+    .to_string()
+}
@@ -3,11 +3,16 @@ mod test {
     use crate::error::Error::*;
     use crate::error::ErrorHandler;
     use crate::error::*;
+    use crate::json;
     use crate::lexer::get_file_buf;
     use crate::lexer::Lexer;
     use crate::lexer::TokenSort::*;
     use crate::parser::Parser;
+    use crate::report;
+    use crate::value::Value;
     use crate::vm;
+    use crate::vm::Inst::*;
+    use std::time::Duration;
 
     #[test]
     fn test_lexer() {
@@ -15,7 +20,8 @@ mod test {
         let t =
             Lexer::new(get_file_buf("./test/lex.goud", &mut err).unwrap(), &mut err).get_tokens();
 
-        assert_eq!(t.len(), 16);
+        assert_eq!(t.len(), 17);
+        assert_eq!(err.source[1], String::from("id\"string\"42=+?printgetpost"));
         assert_eq!(t[0].sort, TokenId);
         assert_eq!(t[0].content, String::from("id"));
         assert_eq!(t[1].sort, TokenString);
@@ -27,18 +33,19 @@ mod test {
         assert_eq!(t[5].sort, TokenQmark);
         assert_eq!(t[6].sort, TokenId);
         assert_eq!(t[6].content, String::from("printgetpost"));
-        assert_eq!(t[7].sort, TokenId);
-        assert_eq!(t[7].content, String::from("id"));
-        assert_eq!(t[8].sort, TokenString);
-        assert_eq!(t[8].content, String::from("string"));
-        assert_eq!(t[9].sort, TokenNumber);
-        assert_eq!(t[9].content, String::from("42"));
-        assert_eq!(t[10].sort, TokenEq);
-        assert_eq!(t[11].sort, TokenPlus);
-        assert_eq!(t[12].sort, TokenQmark);
-        assert_eq!(t[13].sort, TokenPrint);
-        assert_eq!(t[14].sort, TokenGet);
-        assert_eq!(t[15].sort, TokenPost);
+        assert_eq!(t[7].sort, TokenNewline);
+        assert_eq!(t[8].sort, TokenId);
+        assert_eq!(t[8].content, String::from("id"));
+        assert_eq!(t[9].sort, TokenString);
+        assert_eq!(t[9].content, String::from("string"));
+        assert_eq!(t[10].sort, TokenNumber);
+        assert_eq!(t[10].content, String::from("42"));
+        assert_eq!(t[11].sort, TokenEq);
+        assert_eq!(t[12].sort, TokenPlus);
+        assert_eq!(t[13].sort, TokenQmark);
+        assert_eq!(t[14].sort, TokenPrint);
+        assert_eq!(t[15].sort, TokenGet);
+        assert_eq!(t[16].sort, TokenPost);
     }
 
     #[test]
@@ -54,7 +61,7 @@ mod test {
         )
         .parse();
 
-        assert_eq!(err.errors.len(), 7);
+        assert_eq!(err.errors.len(), 6);
         assert_eq!(err.errors[0], WarningEmptyString((4, 6)));
         assert_eq!(err.errors[1], WarningEscapeSeq((5, 8)));
         assert_eq!(
@@ -82,13 +89,65 @@ mod test {
                 (3, 5)
             )
         );
-        assert_eq!(err.errors[5], ErrorNoParse((3, 6), String::from("?")));
         assert_eq!(
-            err.errors[6],
+            err.errors[5],
             ErrorExpectedToken((6, 6), String::from("a string or a variable"))
         );
     }
 
+    #[test]
+    fn test_recovery() {
+        let mut err = ErrorHandler::new(String::from("err2.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/err2.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        // one error per wrong statement, the rest of the file is still parsed
+        assert_eq!(err.errors.len(), 3);
+        assert_eq!(
+            err.errors[0],
+            ErrorUnxepectedToken(
+                Loc {
+                    start: (3, 11),
+                    end: (3, 12)
+                },
+                String::from("a string or a variable"),
+                (3, 10)
+            )
+        );
+        assert_eq!(
+            err.errors[1],
+            ErrorWrongCode(
+                Loc {
+                    start: (8, 31),
+                    end: (8, 35)
+                },
+                String::from("20x0")
+            )
+        );
+        assert_eq!(
+            err.errors[2],
+            ErrorUnxepectedToken(
+                Loc {
+                    start: (14, 5),
+                    end: (14, 6)
+                },
+                String::from("a string or a variable"),
+                (14, 4)
+            )
+        );
+        assert_eq!(prg[2], InstIter(2));
+        assert_eq!(prg[14], InstJump(17));
+        assert_eq!(prg[17], InstPush(Value::from("end")));
+        assert_eq!(prg[18], InstPrint);
+    }
+
     #[test]
     fn test_vm() {
         let mut err = ErrorHandler::new(String::from("vm.goud"));
@@ -98,8 +157,952 @@ mod test {
         )
         .parse();
 
-        let res = vm::Vm::new(prg, true, true, false).execute();
+        let res = vm::Vm::new(String::from("test"), prg, true, true, false).execute();
 
         assert_eq!(res, Some((7, 0)));
     }
+
+    #[test]
+    fn test_methods() {
+        let mut err = ErrorHandler::new(String::from("methods.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/methods.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 0);
+        assert_eq!(prg.len(), 14);
+        assert_eq!(
+            prg[3],
+            InstReq(
+                String::from("PATCH"),
+                true,
+                true,
+                0,
+                Loc {
+                    start: (1, 1),
+                    end: (1, 61)
+                }
+            )
+        );
+        assert_eq!(
+            prg[6],
+            InstReq(
+                String::from("HEAD"),
+                false,
+                false,
+                0,
+                Loc {
+                    start: (2, 1),
+                    end: (2, 31)
+                }
+            )
+        );
+        assert_eq!(prg[8], InstPush(Value::Number(204.into())));
+        assert_eq!(
+            prg[9],
+            InstReq(
+                String::from("OPTIONS"),
+                false,
+                false,
+                0,
+                Loc {
+                    start: (3, 1),
+                    end: (3, 38)
+                }
+            )
+        );
+        assert_eq!(
+            prg[12],
+            InstReqandPush(
+                String::from("PURGE"),
+                false,
+                false,
+                0,
+                Loc {
+                    start: (4, 1),
+                    end: (4, 53)
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_path() {
+        let doc = r#"{ "data": { "token": "abc", "users": [{ "id": 4 }], "first name": null } }"#;
+
+        assert_eq!(json::extract(doc, "$.data.token"), Some(serde_json::json!("abc")));
+        assert_eq!(json::extract(doc, "$.data.users[0].id"), Some(serde_json::json!(4)));
+        assert_eq!(
+            json::extract(doc, "$.data[\"first name\"]"),
+            Some(serde_json::Value::Null)
+        );
+        assert_eq!(json::extract(doc, "$.data.missing"), None);
+        assert_eq!(json::extract(doc, "data.token"), None);
+        assert_eq!(json::extract("not json", "$"), None);
+    }
+
+    #[test]
+    fn test_values() {
+        let doc = serde_json::json!({ "id": 42, "tags": ["a", "b"], "next": null });
+        let v = Value::from_json(&doc);
+
+        assert_eq!(v.to_json(), doc);
+        assert_eq!(
+            v.to_string(),
+            String::from(r#"{"id":42,"next":null,"tags":["a","b"]}"#)
+        );
+        assert!(v.contains(&Value::from("tags")));
+        assert!(!v.contains(&Value::from("name")));
+
+        let id = Value::from_json(&doc["id"]);
+        assert_eq!(id, Value::Number(42.into()));
+        assert_eq!(id.to_string(), String::from("42"));
+        assert!(id.equals(&Value::from("42")));
+        assert!(!id.equals(&Value::Number(4.into())));
+        assert_eq!(id.as_integer(), Some(42));
+
+        // ids above 2^53 are kept exact
+        let big = Value::from_json(&serde_json::json!(9007199254740993u64));
+        assert_eq!(big.to_string(), String::from("9007199254740993"));
+        assert_eq!(big.as_integer(), Some(9007199254740993));
+        assert!(!big.equals(&Value::Number(9007199254740992u64.into())));
+        assert!(Value::Number(3.into()).equals(&Value::from_json(&serde_json::json!(3.0))));
+
+        let tags = Value::from_json(&doc["tags"]);
+        assert!(tags.contains(&Value::from("b")));
+        assert!(!tags.contains(&Value::from("c")));
+        assert!(Value::Null.equals(&Value::from("null")));
+        assert!(!Value::Null.truthy());
+        assert!(Value::Bool(true).truthy());
+        assert!(!Value::List(Vec::new()).truthy());
+    }
+
+    #[test]
+    fn test_json_diff() {
+        let expected =
+            serde_json::from_str(r#"{ "name": "apple", "point": 3, "tags": ["a"] }"#).unwrap();
+        let actual =
+            serde_json::from_str(r#"{"point":3.0,"tags":["b"],"name":"apple","id":1}"#).unwrap();
+
+        assert_eq!(
+            json::diff(&expected, &actual, true),
+            vec!["$.tags[0]: expected \"a\", found \"b\""]
+        );
+        assert_eq!(
+            json::diff(&expected, &actual, false),
+            vec!["$.tags[0]: expected \"a\", found \"b\"", "$.id: unexpected"]
+        );
+        assert_eq!(
+            json::diff(&actual, &expected, true),
+            vec!["$.id: missing", "$.tags[0]: expected \"b\", found \"a\""]
+        );
+    }
+
+    #[test]
+    fn test_headers() {
+        let mut err = ErrorHandler::new(String::from("headers.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/headers.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!(
+            err.errors[0],
+            ErrorExpectedToken((5, 25), String::from("an equal sign or a question mark"))
+        );
+        assert_eq!(
+            prg[5],
+            InstExpectHeader(Loc {
+                start: (2, 1),
+                end: (2, 34)
+            })
+        );
+        assert_eq!(
+            prg[7],
+            InstHeader(Loc {
+                start: (3, 1),
+                end: (3, 31)
+            })
+        );
+        assert_eq!(prg[8], InstLoad(String::from("type")));
+
+        // without a response, reading a header fails and is recorded
+        let prg = vec![
+            InstPush(Value::from("location")),
+            InstHeader(Loc {
+                start: (1, 1),
+                end: (1, 22),
+            }),
+            InstLoad(String::from("url")),
+        ];
+        let mut v = vm::Vm::new(String::from("test"), prg, true, true, false);
+        assert_eq!(v.execute(), Some((0, 1)));
+        assert_eq!(v.records.len(), 1);
+        assert_eq!(v.records[0].method, String::from("header"));
+        assert_eq!(
+            v.records[0].reason,
+            Some(String::from("No header `location` in the last response"))
+        );
+
+        // every assertion is recorded, so the TAP plan matches the counters
+        let prg = vec![InstExpectHeader(Loc {
+            start: (1, 1),
+            end: (1, 34),
+        })];
+        let mut v = vm::Vm::new(String::from("test"), prg.clone(), true, true, false);
+        assert_eq!(v.execute(), Some((0, 1)));
+        assert_eq!(v.records.len(), 1);
+
+        // blocking mode returns after the first failure instead of exiting
+        let mut v = vm::Vm::new(String::from("test"), [prg.clone(), prg].concat(), true, true, true);
+        assert_eq!(v.execute(), Some((0, 1)));
+    }
+
+    #[test]
+    fn test_codes() {
+        let mut err = ErrorHandler::new(String::from("codes.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/codes.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!(
+            err.errors[0],
+            ErrorWrongCode(
+                Loc {
+                    start: (4, 38),
+                    end: (4, 46)
+                },
+                String::from("299..200")
+            )
+        );
+        assert_eq!(prg[1], InstPush(Value::from("2xx")));
+        assert_eq!(prg[4], InstPush(Value::from("200..299")));
+        assert_eq!(prg[7], InstPush(Value::from("200|201|204")));
+
+        assert!(vm::match_code("2xx", "204"));
+        assert!(!vm::match_code("2xx", "404"));
+        assert!(vm::match_code("200..299", "299"));
+        assert!(!vm::match_code("200..299", "300"));
+        assert!(vm::match_code("200|201|204", "201"));
+        assert!(!vm::match_code("200|201|204", "202"));
+    }
+
+    #[test]
+    fn test_if() {
+        let mut err = ErrorHandler::new(String::from("if.goud"));
+        let prg = Parser::new(
+            Lexer::new(get_file_buf("./test/if.goud", &mut err).unwrap(), &mut err).get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 0);
+        assert_eq!(
+            prg,
+            vec![
+                InstPush(Value::from("42")),
+                InstLoad(String::from("id")),
+                InstGain(
+                    String::from("id"),
+                    Loc {
+                        start: (2, 4),
+                        end: (2, 6)
+                    }
+                ),
+                InstPush(Value::from("42")),
+                InstEq,
+                InstJumpIfNot(9),
+                InstPush(Value::from("created")),
+                InstPrint,
+                InstJump(18),
+                InstGain(
+                    String::from("id"),
+                    Loc {
+                        start: (4, 11),
+                        end: (4, 13)
+                    }
+                ),
+                InstPush(Value::from("4")),
+                InstContains,
+                InstJumpIfNot(16),
+                InstPush(Value::from("maybe")),
+                InstPrint,
+                InstJump(18),
+                InstPush(Value::from("nothing")),
+                InstPrint,
+                InstGain(
+                    String::from("id"),
+                    Loc {
+                        start: (9, 4),
+                        end: (9, 6)
+                    }
+                ),
+                InstPush(Value::from("0")),
+                InstNeq,
+                InstJumpIfNot(24),
+                InstGain(
+                    String::from("id"),
+                    Loc {
+                        start: (9, 22),
+                        end: (9, 24)
+                    }
+                ),
+                InstPrint,
+            ]
+        );
+        assert_eq!(
+            vm::Vm::new(String::from("test"), prg, true, true, false).execute(),
+            Some((0, 0))
+        );
+    }
+
+    #[test]
+    fn test_for() {
+        let mut err = ErrorHandler::new(String::from("for.goud"));
+        let prg = Parser::new(
+            Lexer::new(get_file_buf("./test/for.goud", &mut err).unwrap(), &mut err).get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 0);
+        assert_eq!(prg[3], InstIter(3));
+        assert_eq!(prg[4], InstNext(String::from("id"), 8));
+        assert_eq!(prg[7], InstJump(4));
+        assert_eq!(
+            prg[12],
+            InstIterRange(Loc {
+                start: (5, 1),
+                end: (5, 17)
+            })
+        );
+        assert_eq!(prg[13], InstNext(String::from("i"), 17));
+        assert_eq!(prg[17], InstIter(0));
+        assert_eq!(
+            vm::Vm::new(String::from("test"), prg, true, true, false).execute(),
+            Some((0, 0))
+        );
+    }
+
+    #[test]
+    fn test_fn() {
+        let mut err = ErrorHandler::new(String::from("fn.goud"));
+        let prg = Parser::new(
+            Lexer::new(get_file_buf("./test/fn.goud", &mut err).unwrap(), &mut err).get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 4);
+        assert_eq!(
+            err.errors[0],
+            ErrorNullVar(
+                Loc {
+                    start: (11, 7),
+                    end: (11, 11)
+                },
+                String::from("name")
+            )
+        );
+        assert_eq!(
+            err.errors[1],
+            ErrorWrongArgs(
+                Loc {
+                    start: (12, 1),
+                    end: (12, 6)
+                },
+                String::from("greet"),
+                2
+            )
+        );
+        assert_eq!(
+            err.errors[2],
+            ErrorUnknownFn(
+                Loc {
+                    start: (13, 1),
+                    end: (13, 8)
+                },
+                String::from("unknown")
+            )
+        );
+        assert_eq!(
+            err.errors[3],
+            ErrorReturnOutsideFn(Loc {
+                start: (14, 1),
+                end: (14, 7)
+            })
+        );
+        assert_eq!(prg[0], InstJump(17));
+        assert_eq!(prg[1], InstParam(String::from("greeting")));
+        assert_eq!(prg[2], InstParam(String::from("name")));
+        assert_eq!(prg[16], InstRet);
+        assert_eq!(prg[19], InstCall(1));
+    }
+
+    #[test]
+    fn test_ret() {
+        let mut err = ErrorHandler::new(String::from("ret.goud"));
+        let prg = Parser::new(
+            Lexer::new(get_file_buf("./test/ret.goud", &mut err).unwrap(), &mut err).get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 0);
+        // the return drops the iterator of the loop in `first`, not the one of the caller
+        assert_eq!(
+            vm::Vm::new(String::from("test"), prg, true, true, false).execute(),
+            Some((0, 0))
+        );
+    }
+
+    #[test]
+    fn test_include() {
+        let mut err = ErrorHandler::new(String::from("./test/include.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/include.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(
+            err.errors,
+            vec![ErrorInclude(
+                Loc {
+                    start: (4, 9),
+                    end: (4, 31)
+                },
+                String::from("include/missing.goud")
+            )]
+        );
+        assert_eq!(err.includes.len(), 1);
+        assert_eq!(err.includes[0].file, "./test/include/common.goud");
+        assert_eq!(err.includes[0].errors.len(), 2);
+        assert_eq!(
+            err.includes[0].errors[1],
+            ErrorIncludeCycle(
+                Loc {
+                    start: (4, 8),
+                    end: (4, 25)
+                },
+                String::from("../include.goud")
+            )
+        );
+        assert_eq!(prg[0], InstPush(Value::from("https://httpbin.org")));
+        assert_eq!(prg[2], InstJump(9));
+        assert_eq!(prg[12], InstCall(3));
+    }
+
+    #[test]
+    fn test_sources() {
+        let mut err = ErrorHandler::new(String::from("./test/sources.goud"));
+        let mut p = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/sources.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        );
+        let prg = p.parse();
+        let sources = p.sources();
+
+        assert_eq!(
+            sources,
+            vec![
+                (0, String::from("./test/sources.goud")),
+                (2, String::from("./test/include/check.goud")),
+                (7, String::from("./test/sources.goud")),
+            ]
+        );
+
+        // the failures are located in the file of the instruction
+        let mut v = vm::Vm::new(String::from("./test/sources.goud"), prg, true, true, false);
+        v.sources(sources);
+        assert_eq!(v.execute(), Some((0, 2)));
+        assert_eq!(v.records[0].file, String::from("./test/include/check.goud"));
+        assert_eq!(v.records[0].loc.start, (2, 1));
+        assert_eq!(v.records[1].file, String::from("./test/sources.goud"));
+        assert_eq!(v.records[1].loc.start, (3, 1));
+    }
+
+    #[test]
+    fn test_env() {
+        let mut err = ErrorHandler::new(String::from("env.goud"));
+        let mut p = Parser::new(
+            Lexer::new(get_file_buf("./test/env.goud", &mut err).unwrap(), &mut err).get_tokens(),
+            &mut err,
+        );
+        p.define(String::from("base"));
+        let prg = p.parse();
+
+        assert_eq!(err.errors.len(), 0);
+        assert_eq!(prg[3], InstPush(Value::from("API_TOKEN")));
+        assert_eq!(
+            prg[4],
+            InstEnv(Loc {
+                start: (1, 20),
+                end: (1, 30)
+            })
+        );
+        assert_eq!(
+            prg[9],
+            InstEnv(Loc {
+                start: (1, 33),
+                end: (1, 54)
+            })
+        );
+
+        let mut v = vm::Vm::new(String::from("test"), prg, true, true, false);
+        v.define(String::from("base"), String::from("http://localhost"));
+        assert_eq!(v.execute(), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_arith() {
+        let mut err = ErrorHandler::new(String::from("arith.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/arith.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 0);
+        // (page - 1) * 20 + 5 % 3
+        assert_eq!(
+            prg[4],
+            InstMinus(Loc {
+                start: (2, 11),
+                end: (2, 19)
+            })
+        );
+        assert_eq!(
+            prg[6],
+            InstMul(Loc {
+                start: (2, 10),
+                end: (2, 25)
+            })
+        );
+        assert_eq!(
+            prg[9],
+            InstMod(Loc {
+                start: (2, 28),
+                end: (2, 33)
+            })
+        );
+        assert_eq!(
+            prg[10],
+            InstPlus(Loc {
+                start: (2, 10),
+                end: (2, 33)
+            })
+        );
+        // -page + 10 / 3
+        assert_eq!(
+            prg[13],
+            InstNeg(Loc {
+                start: (3, 9),
+                end: (3, 14)
+            })
+        );
+        assert_eq!(
+            prg[16],
+            InstDiv(Loc {
+                start: (3, 17),
+                end: (3, 23)
+            })
+        );
+
+        let mut v = vm::Vm::new(String::from("test"), prg, true, true, false);
+        // 7 / 0 and "a" * 2 fail
+        assert_eq!(v.execute(), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut err = ErrorHandler::new(String::from("interp.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/interp.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 3);
+        assert_eq!(err.errors[0], ErrorInterpolation((6, 23)));
+        assert_eq!(err.errors[1], ErrorInterpolation((6, 32)));
+        assert_eq!(
+            err.errors[2],
+            ErrorNullVar(
+                Loc {
+                    start: (6, 10),
+                    end: (6, 17)
+                },
+                String::from("missing")
+            )
+        );
+        // "${base}/users/${id}/orders"
+        assert_eq!(
+            prg[4..13],
+            [
+                InstPush(Value::from("")),
+                InstGain(
+                    String::from("base"),
+                    Loc {
+                        start: (3, 10),
+                        end: (3, 14)
+                    }
+                ),
+                InstConcat,
+                InstPush(Value::from("/users/")),
+                InstConcat,
+                InstGain(
+                    String::from("id"),
+                    Loc {
+                        start: (3, 24),
+                        end: (3, 26)
+                    }
+                ),
+                InstConcat,
+                InstPush(Value::from("/orders")),
+                InstConcat,
+            ]
+        );
+        assert_eq!(prg[17], InstPush(Value::from(", \"price\": \"${id}\" }")));
+    }
+
+    #[test]
+    fn test_plain_string() {
+        let mut err = ErrorHandler::new(String::from("plain.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/plain.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(
+            err.errors,
+            vec![
+                ErrorPlainString(
+                    Loc {
+                        start: (2, 9),
+                        end: (2, 29)
+                    },
+                    String::from("a file name")
+                ),
+                ErrorPlainString(
+                    Loc {
+                        start: (3, 9),
+                        end: (3, 17)
+                    },
+                    String::from("a method string")
+                ),
+                ErrorPlainString(
+                    Loc {
+                        start: (4, 35),
+                        end: (4, 48)
+                    },
+                    String::from("a body file name")
+                ),
+            ]
+        );
+        assert_eq!(prg[prg.len() - 1], InstPrint);
+    }
+
+    #[test]
+    fn test_multiline() {
+        let mut err = ErrorHandler::new(String::from("multiline.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/multiline.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 0);
+        assert_eq!(prg[2], InstPush(Value::from("{\n    \"name\": \"")));
+        assert_eq!(
+            prg[3],
+            InstGain(
+                String::from("name"),
+                Loc {
+                    start: (4, 20),
+                    end: (4, 24)
+                }
+            )
+        );
+        assert_eq!(
+            prg[5],
+            InstPush(Value::from("\",\n    \"tags\": [\"a\", \"b\"]\n}"))
+        );
+        assert_eq!(prg[10], InstPush(Value::from("one\n  two")));
+        assert_eq!(prg[12], InstPush(Value::from("\"quoted\" ${x}")));
+    }
+
+    #[test]
+    fn test_body_file() {
+        let mut err = ErrorHandler::new(String::from("./test/body.goud"));
+        let prg = Parser::new(
+            Lexer::new(
+                get_file_buf("./test/body.goud", &mut err).unwrap(),
+                &mut err,
+            )
+            .get_tokens(),
+            &mut err,
+        )
+        .parse();
+
+        assert_eq!(err.errors.len(), 1);
+        assert_eq!(
+            err.errors[0],
+            ErrorBodyFile(
+                Loc {
+                    start: (4, 36),
+                    end: (4, 64)
+                },
+                String::from("fixtures/missing.json")
+            )
+        );
+        assert_eq!(
+            prg.iter()
+                .filter(|i| **i == InstPush(Value::from("{ \"name\": \"apple\" }\n")))
+                .count(),
+            2
+        );
+        // `file` is still a variable when it's not followed by a path
+        assert_eq!(prg.last(), Some(&InstPrint));
+        assert_eq!(
+            prg[prg.len() - 2],
+            InstGain(
+                String::from("file"),
+                Loc {
+                    start: (5, 7),
+                    end: (5, 11)
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(vm::truncate("hello", 10), String::from("hello"));
+        assert_eq!(vm::truncate("hello", 0), String::from("hello"));
+        assert_eq!(
+            vm::truncate("hello world", 5),
+            String::from("hello... (6 more characters)")
+        );
+    }
+
+    #[test]
+    fn test_curl() {
+        assert_eq!(
+            report::curl("GET", "https://example.com", "", false, &[]),
+            String::from("curl 'https://example.com'")
+        );
+        assert_eq!(
+            report::curl(
+                "POST",
+                "https://example.com/users",
+                "{ \"name\": \"it's\" }",
+                true,
+                &[String::from("x-token: abc")]
+            ),
+            String::from(
+                "curl -X POST 'https://example.com/users' -H 'content-type: application/json' -H 'x-token: abc' --data-raw '{ \"name\": \"it'\\''s\" }'"
+            )
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let prg = vec![
+            InstPush(Value::from("url")),
+            InstPush(Value::from("2xx")),
+            InstReqandCompareJson(
+                String::from("POST"),
+                true,
+                true,
+                1,
+                true,
+                Loc {
+                    start: (3, 1),
+                    end: (3, 20),
+                },
+            ),
+            InstJumpIfNot(0),
+        ];
+        let sources = vec![
+            (0, String::from("api.goud")),
+            (2, String::from("include/check.goud")),
+            (3, String::from("api.goud")),
+        ];
+
+        assert_eq!(
+            vm::disassemble(&prg, &sources[..1]),
+            String::from(
+                "0000  push          \"url\"\n\
+                 0001  push          \"2xx\"\n\
+                 0002  req_json      POST json headers=1 subset               ; 3:1\n\
+                 0003  jump_if_not   0\n"
+            )
+        );
+        assert_eq!(
+            vm::disassemble(&prg, &sources),
+            String::from(
+                "0000  push          \"url\"\n\
+                 0001  push          \"2xx\"\n\
+                 ; include/check.goud\n\
+                 0002  req_json      POST json headers=1 subset               ; 3:1\n\
+                 ; api.goud\n\
+                 0003  jump_if_not   0\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_reports() {
+        let records = vec![
+            vm::Record {
+                method: String::from("GET"),
+                url: String::from("https://httpbin.org/get?a=1&b=2"),
+                file: String::from("api.goud"),
+                loc: Loc {
+                    start: (1, 1),
+                    end: (1, 36),
+                },
+                expected: String::from("200"),
+                code: Some(String::from("200")),
+                reason: None,
+                details: Vec::new(),
+                duration: Duration::from_millis(12),
+            },
+            vm::Record {
+                method: String::from("POST"),
+                url: String::from("https://httpbin.org/post"),
+                file: String::from("api.goud"),
+                loc: Loc {
+                    start: (2, 1),
+                    end: (2, 36),
+                },
+                expected: String::from("2xx"),
+                code: None,
+                reason: Some(String::from("Unable to make request")),
+                details: Vec::new(),
+                duration: Duration::from_millis(3),
+            },
+            vm::Record {
+                method: String::from("header"),
+                url: String::from("location"),
+                file: String::from("api.goud"),
+                loc: Loc {
+                    start: (3, 1),
+                    end: (3, 34),
+                },
+                expected: String::from("/login"),
+                code: None,
+                reason: Some(String::from("No header `location` in the last response")),
+                details: Vec::new(),
+                duration: Duration::ZERO,
+            },
+        ];
+        let xml = report::junit(&[(String::from("api.goud"), records.clone())]);
+
+        assert!(xml.contains("<testsuites name=\"goudron\" tests=\"3\" failures=\"2\">"));
+        assert!(xml.contains("name=\"GET https://httpbin.org/get?a=1&amp;b=2\" file=\"api.goud\" line=\"1\" time=\"0.012\""));
+        assert!(xml.contains(
+            "<failure message=\"Unable to make request\">expected code: 2xx\nactual code: none</failure>"
+        ));
+        assert!(xml.contains("name=\"header location\" file=\"api.goud\" line=\"3\""));
+        assert!(xml.contains("expected value: /login\nactual value: none</failure>"));
+        assert_eq!(
+            report::tap(2, "api.goud", &records[1]),
+            "not ok 2 - api.goud: POST https://httpbin.org/post\n  ---\n  message: \"Unable to make request\"\n  at: \"api.goud:2:1\"\n  expected: \"2xx\"\n  found: null\n  duration_ms: 3\n  ..."
+        );
+        assert_eq!(
+            report::json(&records[0]),
+            r#"{"code":"200","column":1,"details":[],"duration_ms":12,"expected":"200","file":"api.goud","line":1,"method":"GET","ok":true,"reason":null,"url":"https://httpbin.org/get?a=1&b=2"}"#
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let err = ErrorHandler::new(String::from("api.goud"));
+        let source = vec![String::from("\tid = ?")];
+
+        assert_eq!(
+            err.render(
+                &ErrorUnxepectedToken(
+                    Loc {
+                        start: (1, 7),
+                        end: (1, 8)
+                    },
+                    String::from("a string or a variable"),
+                    (1, 6)
+                ),
+                &source,
+                false
+            ),
+            "error: Unexpected token\n --> api.goud:1:7\n  |\n1 | \tid = ?\n  | \t     ^\n  = note: a string or a variable is expected at api.goud:1:6"
+        );
+        assert_eq!(
+            err.render(&ErrorWrongPath, &source, false),
+            "error: api.goud: No such file or directory"
+        );
+        // the columns are bytes, `é` takes two of them but one character
+        assert_eq!(
+            err.render(
+                &ErrorNullVar(
+                    Loc {
+                        start: (1, 15),
+                        end: (1, 19)
+                    },
+                    String::from("name")
+                ),
+                &[String::from("x = \"café\" + name")],
+                false
+            ),
+            "error: Variable `name` has no value\n --> api.goud:1:15\n  |\n1 | x = \"café\" + name\n  |              ^^^^"
+        );
+        assert_eq!(
+            err.render(&WarningEmptyString((1, 2)), &source, true),
+            "\x1b[1;33mwarning\x1b[0m\x1b[1m: Empty string\x1b[0m\n \x1b[1;34m-->\x1b[0m api.goud:1:2\n  \x1b[1;34m|\x1b[0m\n\x1b[1;34m1\x1b[0m \x1b[1;34m|\x1b[0m \tid = ?\n  \x1b[1;34m|\x1b[0m \t\x1b[1;33m^\x1b[0m"
+        );
+    }
 }
@@ -0,0 +1,134 @@
+use crate::json;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// A value of the script, on the stack or in a variable
+#[derive(PartialEq, Clone, Debug)]
+pub enum Value {
+    String(String),
+    Number(serde_json::Number), // exact for integers, even above 2^53
+    Bool(bool),
+    Null,
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    // A number literal, integers are kept exact
+    pub fn parse_number(s: &str) -> Option<Self> {
+        if let Ok(n) = s.parse::<i64>() {
+            return Some(Value::Number(n.into()));
+        }
+        if let Ok(n) = s.parse::<u64>() {
+            return Some(Value::Number(n.into()));
+        }
+        s.parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+    }
+
+    pub fn from_json(json: &serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::String(s) => Value::String(s.clone()),
+            serde_json::Value::Number(n) => Value::Number(n.clone()),
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Array(a) => Value::List(a.iter().map(Value::from_json).collect()),
+            serde_json::Value::Object(o) => Value::Map(
+                o.iter()
+                    .map(|(k, v)| (k.clone(), Value::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Number(n) => serde_json::Value::Number(n.clone()),
+            Value::Bool(b) => serde_json::Value::Bool(*b),
+            Value::Null => serde_json::Value::Null,
+            Value::List(l) => serde_json::Value::Array(l.iter().map(|v| v.to_json()).collect()),
+            Value::Map(m) => {
+                serde_json::Value::Object(m.iter().map(|(k, v)| (k.clone(), v.to_json())).collect())
+            }
+        }
+    }
+
+    // A number without decimals, or a string made of one
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Some(i),
+                (None, Some(f)) if n.is_f64() && f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                    Some(f as i64)
+                }
+                _ => None,
+            },
+            Value::String(s) => s.parse::<i64>().ok(),
+            _ => None,
+        }
+    }
+
+    // The value of a condition
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::String(s) => !s.is_empty(),
+            Value::Number(n) => n.as_f64() != Some(0.0),
+            Value::Bool(b) => *b,
+            Value::Null => false,
+            Value::List(l) => !l.is_empty(),
+            Value::Map(m) => !m.is_empty(),
+        }
+    }
+
+    // Values of different types are compared as they are printed, so `id == "42"` holds for the number 42
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => json::same_number(a, b),
+            (Value::String(_), Value::String(_))
+            | (Value::Bool(_), Value::Bool(_))
+            | (Value::Null, Value::Null)
+            | (Value::List(_), Value::List(_))
+            | (Value::Map(_), Value::Map(_)) => self == other,
+            _ => self.to_string() == other.to_string(),
+        }
+    }
+
+    // A substring, an element of a list or a key of a map
+    pub fn contains(&self, other: &Value) -> bool {
+        match self {
+            Value::List(l) => l.iter().any(|v| v.equals(other)),
+            Value::Map(m) => m.contains_key(other.to_string().as_str()),
+            _ => self.to_string().contains(other.to_string().as_str()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", s),
+            _ => write!(f, "{}", self.to_json()),
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(String::from(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::String(String::new())
+    }
+}
@@ -1,52 +1,269 @@
+use crate::error::Loc;
+use crate::json;
+use crate::report;
+use crate::report::Format;
+use crate::value::Value;
 use curl::easy::Easy;
 use curl::easy::List;
 use std::collections::HashMap;
 use std::io::Read;
-use std::process::exit;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use std::time::Instant;
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Inst {
-    InstPush(String),
+    InstPush(Value),
     InstLoad(String),
-    InstGain(String),
-    InstPlus,
+    InstGain(String, Loc),
+    InstPlus(Loc),
+    InstConcat,
+    InstMinus(Loc),
+    InstMul(Loc),
+    InstDiv(Loc),
+    InstMod(Loc),
+    InstNeg(Loc),
     InstPrint,
-    InstReq(String, bool, bool, i32),
-    InstReqandPush(String, bool, bool, i32),
-    InstReqandCompare(String, bool, bool, i32),
+    InstReq(String, bool, bool, i32, Loc),
+    InstReqandPush(String, bool, bool, i32, Loc),
+    InstReqandCompare(String, bool, bool, i32, Loc),
+    InstReqandExtract(String, bool, bool, i32, Loc),
+    InstReqandCompareJson(String, bool, bool, i32, bool, Loc),
+    InstHeader(Loc),
+    InstExpectHeader(Loc),
+    InstEq,
+    InstNeq,
+    InstContains,
+    InstJump(usize),
+    InstJumpIfNot(usize),
+    InstIter(usize),
+    InstIterRange(Loc),
+    InstNext(String, usize),
+    InstCall(usize),
+    InstParam(String),
+    InstRet,
+    InstEnv(Loc),
 }
 
+// Check a response code against an expectation like `200`, `2xx`, `200..299` or `200|201|204`
+pub fn match_code(expected: &str, code: &str) -> bool {
+    expected.split('|').any(|alt| match alt.split_once("..") {
+        Some((low, high)) => match (low.parse::<u32>(), high.parse::<u32>(), code.parse::<u32>()) {
+            (Ok(l), Ok(h), Ok(c)) => l <= c && c <= h,
+            _ => false,
+        },
+        None => {
+            alt.len() == code.len()
+                && alt
+                    .chars()
+                    .zip(code.chars())
+                    .all(|(e, c)| e == 'x' || e == c)
+        }
+    })
+}
+
+// Cut a body after `limit` characters, 0 means no limit
+pub fn truncate(s: &str, limit: usize) -> String {
+    let len = s.chars().count();
+
+    if limit == 0 || len <= limit {
+        String::from(s)
+    } else {
+        let cut: String = s.chars().take(limit).collect();
+        format!("{}... ({} more characters)", cut, len - limit)
+    }
+}
+
+// The outcome of a request made by the script
+#[derive(PartialEq, Clone, Debug)]
+pub struct Record {
+    pub method: String,
+    pub url: String,
+    pub expected: String,
+    pub file: String, // the script or the included file of the instruction
+    pub loc: Loc,
+    pub code: Option<String>,   // None when the request couldn't be made
+    pub reason: Option<String>, // None when the route is valid
+    pub details: Vec<String>,
+    pub duration: Duration,
+}
+
+impl Inst {
+    // The location in the script of the instructions that can fail at runtime
+    pub fn loc(&self) -> Option<&Loc> {
+        match self {
+            Inst::InstGain(_, l)
+            | Inst::InstReq(_, _, _, _, l)
+            | Inst::InstReqandPush(_, _, _, _, l)
+            | Inst::InstReqandCompare(_, _, _, _, l)
+            | Inst::InstReqandExtract(_, _, _, _, l)
+            | Inst::InstReqandCompareJson(_, _, _, _, _, l)
+            | Inst::InstHeader(l)
+            | Inst::InstExpectHeader(l)
+            | Inst::InstPlus(l)
+            | Inst::InstMinus(l)
+            | Inst::InstMul(l)
+            | Inst::InstDiv(l)
+            | Inst::InstMod(l)
+            | Inst::InstNeg(l)
+            | Inst::InstIterRange(l)
+            | Inst::InstEnv(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    // The name of the instruction and its operands, for the bytecode listing
+    fn describe(&self) -> (&'static str, String) {
+        let req = |m: &String, b: &bool, j: &bool, h: &i32| {
+            let mut res = m.clone();
+
+            if *b {
+                res.push_str(if *j { " json" } else { " body" });
+            }
+            if *h > 0 {
+                res.push_str(format!(" headers={}", h).as_str());
+            }
+            res
+        };
+
+        match self {
+            Inst::InstPush(Value::String(s)) => ("push", format!("{:?}", s)),
+            Inst::InstPush(v) => ("push", v.to_string()),
+            Inst::InstLoad(s) => ("load", s.clone()),
+            Inst::InstGain(s, _) => ("gain", s.clone()),
+            Inst::InstPlus(_) => ("plus", String::new()),
+            Inst::InstConcat => ("concat", String::new()),
+            Inst::InstMinus(_) => ("minus", String::new()),
+            Inst::InstMul(_) => ("mul", String::new()),
+            Inst::InstDiv(_) => ("div", String::new()),
+            Inst::InstMod(_) => ("mod", String::new()),
+            Inst::InstNeg(_) => ("neg", String::new()),
+            Inst::InstPrint => ("print", String::new()),
+            Inst::InstReq(m, b, j, h, _) => ("req", req(m, b, j, h)),
+            Inst::InstReqandPush(m, b, j, h, _) => ("req_push", req(m, b, j, h)),
+            Inst::InstReqandCompare(m, b, j, h, _) => ("req_compare", req(m, b, j, h)),
+            Inst::InstReqandExtract(m, b, j, h, _) => ("req_extract", req(m, b, j, h)),
+            Inst::InstReqandCompareJson(m, b, j, h, subset, _) => (
+                "req_json",
+                req(m, b, j, h) + if *subset { " subset" } else { "" },
+            ),
+            Inst::InstHeader(_) => ("header", String::new()),
+            Inst::InstExpectHeader(_) => ("expect_header", String::new()),
+            Inst::InstEq => ("eq", String::new()),
+            Inst::InstNeq => ("neq", String::new()),
+            Inst::InstContains => ("contains", String::new()),
+            Inst::InstJump(a) => ("jump", a.to_string()),
+            Inst::InstJumpIfNot(a) => ("jump_if_not", a.to_string()),
+            Inst::InstIter(n) => ("iter", n.to_string()),
+            Inst::InstIterRange(_) => ("iter_range", String::new()),
+            Inst::InstNext(s, a) => ("next", format!("{} {}", s, a)),
+            Inst::InstCall(a) => ("call", a.to_string()),
+            Inst::InstParam(s) => ("param", s.clone()),
+            Inst::InstRet => ("ret", String::new()),
+            Inst::InstEnv(_) => ("env", String::new()),
+        }
+    }
+}
+
+// A readable listing of a compiled script : address, instruction, operands and location,
+// the name of the file is written before the instructions of each included file
+pub fn disassemble(prg: &[Inst], sources: &[(usize, String)]) -> String {
+    let mut res = String::new();
+    let mut file = sources.first().map(|(_, f)| f); // the script, named by the caller
+
+    for (i, inst) in prg.iter().enumerate() {
+        if let Some(s) = sources.iter().rposition(|(a, _)| *a <= i) {
+            if file != Some(&sources[s].1) {
+                file = Some(&sources[s].1);
+                res.push_str(format!("; {}\n", sources[s].1).as_str());
+            }
+        }
+
+        let (name, operands) = inst.describe();
+        let mut line = format!("{:04}  {:<14}{}", i, name, operands);
+
+        if let Some(l) = inst.loc() {
+            line = format!("{:<60} ; {}:{}", line, l.start.0, l.start.1);
+        }
+        res.push_str(line.trim_end());
+        res.push('\n');
+    }
+
+    res
+}
+
+// The elements of a `for` loop, a range is only computed as the loop goes
+enum Iter {
+    List(Vec<Value>, usize), // (elements, next element)
+    Range(RangeInclusive<i64>),
+}
+
+// The body, the status code and the headers of a response
+type Response = (String, String, Vec<(String, String)>);
+
 pub struct Vm {
-    stack: Vec<String>,
-    var: HashMap<String, String>,
+    stack: Vec<Value>,
+    frames: Vec<HashMap<String, Value>>, // global variables, then one frame per call
+    calls: Vec<(usize, usize)>,          // (return address, iterators of the caller)
+    iters: Vec<Iter>,
     prg: Vec<Inst>,
+    route: String,
+    response_headers: Vec<(String, String)>,
     state: bool,
     ok: usize,
     err: usize,
     silent: bool,
     quiet: bool,
     blocking: bool,
+    pub records: Vec<Record>,
+    elapsed: Duration,
+    file: String,
+    sources: Vec<(usize, String)>, // (first address, file) of the parts of the program
+    source: usize,                 // part of the running instruction
+    loc: Loc,                      // location of the running instruction
+    format: Format,
+    count: usize,           // records already streamed by previous files
+    verbose: Option<usize>, // print the exchanges, bodies cut after this many characters
+    dry_run: bool,          // print the requests as curl commands instead of making them
 }
 
 impl Vm {
-    pub fn new(prg: Vec<Inst>, silent: bool, quiet: bool, blocking: bool) -> Self {
+    pub fn new(file: String, prg: Vec<Inst>, silent: bool, quiet: bool, blocking: bool) -> Self {
         Self {
             stack: Vec::new(),
-            var: HashMap::new(),
+            frames: vec![HashMap::new()],
+            calls: Vec::new(),
+            iters: Vec::new(),
             prg: prg,
+            route: String::new(),
+            response_headers: Vec::new(),
             state: true,
             ok: 0,
             err: 0,
             silent: silent,
             quiet: quiet,
             blocking: blocking,
+            records: Vec::new(),
+            elapsed: Duration::ZERO,
+            sources: vec![(0, file.clone())],
+            source: 0,
+            file,
+            loc: Loc {
+                start: (0, 0),
+                end: (0, 0),
+            },
+            format: Format::Human,
+            count: 0,
+            verbose: None,
+            dry_run: false,
         }
     }
 
-    fn req(url: String, body: String, method: &str, json: bool, http_headers: Vec<String>) -> Option<(String, String)> {
+    fn req(url: String, body: String, method: &str, json: bool, http_headers: Vec<String>) -> Option<Response> {
         let mut res = String::new();
         let code: String;
         let mut data = Vec::new();
+        let mut response_headers: Vec<(String, String)> = Vec::new();
         let mut handle = Easy::new();
         let mut b = Box::leak(body.into_boxed_str()).as_bytes();
         let mut headers = List::new();
@@ -69,7 +286,23 @@ impl Vm {
                 Ok(_) => {}
                 Err(_) => return None,
             },
-            _ => {}
+            "HEAD" => match handle.nobody(true) {
+                Ok(_) => {}
+                Err(_) => return None,
+            },
+            _ => {
+                if !b.is_empty() {
+                    match handle.post(true) {
+                        Ok(_) => {}
+                        Err(_) => return None,
+                    }
+                }
+
+                match handle.custom_request(method) {
+                    Ok(_) => {}
+                    Err(_) => return None,
+                }
+            }
         }
 
         if json {
@@ -107,6 +340,22 @@ impl Vm {
                 Err(_) => return None,
             }
 
+            match transfer.header_function(|header| {
+                let line = String::from_utf8_lossy(header);
+
+                if line.starts_with("HTTP/") {
+                    // a new response begins (redirection, 100 continue...)
+                    response_headers.clear();
+                } else if let Some((name, value)) = line.split_once(':') {
+                    response_headers
+                        .push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+                }
+                true
+            }) {
+                Ok(_) => {}
+                Err(_) => return None,
+            }
+
             match transfer.perform() {
                 Ok(_) => {}
                 Err(_) => return None,
@@ -122,38 +371,447 @@ impl Vm {
             res.push(i as char);
         }
 
-        Some((res, code))
+        Some((res, code, response_headers))
+    }
+
+    fn response_header(&self, name: &str) -> Option<String> {
+        let name = name.to_ascii_lowercase();
+        let values: Vec<&str> = self
+            .response_headers
+            .iter()
+            .filter(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+            .collect();
+
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    // Set a global variable before the execution
+    pub fn define(&mut self, name: String, value: String) {
+        self.frames[0].insert(name, Value::String(value));
+    }
+
+    fn get_var(&self, name: &str) -> Option<Value> {
+        let local = self.frames.len() - 1;
+
+        match self.frames[local].get(name) {
+            Some(v) => Some(v.clone()),
+            None => self.frames[0].get(name).cloned(),
+        }
+    }
+
+    // Variables are global unless they are parameters of the running procedure
+    fn set_var(&mut self, name: &str, value: Value) {
+        let local = self.frames.len() - 1;
+
+        if self.frames[local].contains_key(name) {
+            self.frames[local].insert(name.to_string(), value);
+        } else {
+            self.frames[0].insert(name.to_string(), value);
+        }
+    }
+
+    // The files the instructions come from when the script includes others
+    pub fn sources(&mut self, sources: Vec<(usize, String)>) {
+        self.sources = sources;
+    }
+
+    // Print every request as soon as it's made, numbered after `count`
+    pub fn stream(&mut self, format: Format, count: usize) {
+        self.format = format;
+        self.count = count;
+    }
+
+    // Print every exchange on stderr, a limit of 0 keeps the whole bodies
+    pub fn verbose(&mut self, limit: usize) {
+        self.verbose = Some(limit);
+    }
+
+    // Print a curl command for every request instead of making it
+    pub fn dry_run(&mut self) {
+        self.dry_run = true;
+    }
+
+    // Run an instruction that needs a response without making the request,
+    // the responses and headers are empty. False if it needs no response
+    fn dry_step(&mut self, cursor: usize) -> bool {
+        let (m, b, j, h, operands, result) = match &self.prg[cursor] {
+            Inst::InstReq(m, b, j, h, _) => (m.clone(), *b, *j, *h, 0, false),
+            Inst::InstReqandPush(m, b, j, h, _) => (m.clone(), *b, *j, *h, 0, true),
+            Inst::InstReqandCompare(m, b, j, h, _) => (m.clone(), *b, *j, *h, 1, false),
+            Inst::InstReqandExtract(m, b, j, h, _) => (m.clone(), *b, *j, *h, 1, true),
+            Inst::InstReqandCompareJson(m, b, j, h, _, _) => (m.clone(), *b, *j, *h, 1, false),
+            Inst::InstHeader(_) => {
+                self.pop();
+                self.stack.push(Value::default());
+                return true;
+            }
+            Inst::InstExpectHeader(_) => {
+                self.pop();
+                self.pop();
+                return true;
+            }
+            _ => return false,
+        };
+
+        for _ in 0..operands {
+            self.pop();
+        }
+        let (url, body, headers, _) = self.pop_request(b, h);
+        println!(
+            "{}",
+            report::curl(m.as_str(), url.as_str(), body.as_str(), j, &headers)
+        );
+
+        if result {
+            self.stack.push(Value::default());
+        }
+        true
+    }
+
+    // The position of the running instruction, like `file:line:column`
+    fn position(&self) -> String {
+        format!("{}:{}:{}", self.source_file(), self.loc.start.0, self.loc.start.1)
+    }
+
+    fn source_file(&self) -> String {
+        self.sources[self.source].1.clone()
+    }
+
+    fn record(&mut self, r: Record) {
+        match self.format {
+            Format::Human => {}
+            Format::Tap => println!(
+                "{}",
+                report::tap(self.count + self.records.len() + 1, self.file.as_str(), &r)
+            ),
+            Format::Json => println!("{}", report::json(&r)),
+        }
+
+        self.records.push(r);
+    }
+
+    // Integer arithmetic, null and a failure when it's not possible
+    fn arithmetic(&mut self, op: char, left: &Value, right: &Value) -> Value {
+        let (l, r) = match (left.as_integer(), right.as_integer()) {
+            (Some(l), Some(r)) => (l, r),
+            _ => {
+                if !self.silent {
+                    eprintln!(
+                        "error: {} `{} {} {}` : Only integers can be computed",
+                        self.position(),
+                        left,
+                        op,
+                        right
+                    );
+                }
+                self.err += 1;
+                return Value::Null;
+            }
+        };
+
+        let res = match op {
+            '+' => l.checked_add(r),
+            '-' => l.checked_sub(r),
+            '*' => l.checked_mul(r),
+            '/' => l.checked_div(r),
+            _ => l.checked_rem(r),
+        };
+
+        match res {
+            Some(n) => Value::Number(n.into()),
+            None => {
+                if !self.silent {
+                    eprintln!(
+                        "error: {} `{} {} {}` : {}",
+                        self.position(),
+                        left,
+                        op,
+                        right,
+                        if r == 0 && (op == '/' || op == '%') {
+                            "Division by zero"
+                        } else {
+                            "Integer overflow"
+                        }
+                    );
+                }
+                self.err += 1;
+                Value::Null
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap_or_default()
+    }
+
+    fn pop_string(&mut self) -> String {
+        self.pop().to_string()
+    }
+
+    // Pop the operands of a request : (url, body, headers, expected code)
+    fn pop_request(&mut self, body: bool, headers: i32) -> (String, String, Vec<String>, String) {
+        let expected_code = self.pop_string();
+        let mut h = Vec::new();
+        let mut b = String::from("");
+
+        for _ in 0..headers {
+            h.push(self.pop_string());
+        }
+        h.reverse();
+
+        if body {
+            b = self.pop_string();
+        }
+
+        (self.pop_string(), b, h, expected_code)
+    }
+
+    fn send(
+        &mut self,
+        method: &str,
+        url: &str,
+        body: String,
+        json: bool,
+        headers: Vec<String>,
+    ) -> Option<(String, String)> {
+        self.route = String::from(method) + " " + url;
+        self.response_headers.clear();
+
+        if let Some(limit) = self.verbose {
+            eprintln!("> {} {}", method, url);
+
+            if json {
+                eprintln!("> content-type: application/json");
+            }
+            for h in headers.iter() {
+                eprintln!("> {}", h);
+            }
+            eprintln!(">");
+
+            if !body.is_empty() {
+                eprintln!("{}", truncate(&body, limit));
+            }
+        }
+
+        let start = Instant::now();
+        let res = Vm::req(String::from(url), body, method, json, headers);
+        self.elapsed = start.elapsed();
+
+        if let Some(limit) = self.verbose {
+            match &res {
+                Some((response, code, response_headers)) => {
+                    eprintln!("< {} ({} ms)", code, self.elapsed.as_millis());
+
+                    for (name, value) in response_headers.iter() {
+                        eprintln!("< {}: {}", name, value);
+                    }
+                    eprintln!("<");
+
+                    if !response.is_empty() {
+                        eprintln!("{}", truncate(response, limit));
+                    }
+                }
+                None => eprintln!("< no response"),
+            }
+            eprintln!();
+        }
+
+        match res {
+            Some((response, code, response_headers)) => {
+                self.response_headers = response_headers;
+                Some((response, code))
+            }
+            None => None,
+        }
+    }
+
+    fn route_ok(&mut self, method: String, url: String, expected: String, code: String) {
+        self.ok += 1;
+        self.record(Record {
+            method,
+            url,
+            file: self.source_file(),
+            loc: self.loc.clone(),
+            expected,
+            code: Some(code),
+            reason: None,
+            details: Vec::new(),
+            duration: self.elapsed,
+        });
+    }
+
+    fn route_error(
+        &mut self,
+        method: String,
+        url: String,
+        expected: String,
+        code: Option<String>,
+        reason: &str,
+        details: Vec<String>,
+    ) {
+        if !self.silent {
+            eprintln!(
+                "route error: {} {} {} : {}",
+                self.position(),
+                method,
+                url,
+                reason
+            );
+
+            for d in details.iter() {
+                eprintln!("    {}", d);
+            }
+        }
+
+        self.err += 1;
+        self.record(Record {
+            method,
+            url,
+            file: self.source_file(),
+            loc: self.loc.clone(),
+            expected,
+            code,
+            reason: Some(String::from(reason)),
+            details,
+            duration: self.elapsed,
+        });
+    }
+
+    fn header_ok(&mut self, name: String, expected: String, found: String) {
+        self.ok += 1;
+        self.record(Record {
+            method: String::from("header"),
+            url: name,
+            file: self.source_file(),
+            loc: self.loc.clone(),
+            expected,
+            code: Some(found),
+            reason: None,
+            details: Vec::new(),
+            duration: Duration::ZERO,
+        });
+    }
+
+    // A failed assertion on the headers of the last response, recorded like a request
+    fn header_error(
+        &mut self,
+        name: String,
+        expected: String,
+        found: Option<String>,
+        reason: &str,
+    ) {
+        if !self.silent {
+            eprintln!("route error: {} {} : {}", self.position(), self.route, reason);
+        }
+
+        self.err += 1;
+        self.record(Record {
+            method: String::from("header"),
+            url: name,
+            file: self.source_file(),
+            loc: self.loc.clone(),
+            expected,
+            code: found,
+            reason: Some(String::from(reason)),
+            details: Vec::new(),
+            duration: Duration::ZERO,
+        });
     }
 
     pub fn execute(&mut self) -> Option<(usize, usize)> {
         let mut cursor = 0;
 
         while self.state && cursor < self.prg.len() {
+            if let Some(l) = self.prg[cursor].loc() {
+                self.loc = l.clone();
+
+                if let Some(s) = self.sources.iter().rposition(|(a, _)| *a <= cursor) {
+                    self.source = s;
+                }
+            }
+
+            if self.dry_run && self.dry_step(cursor) {
+                cursor += 1;
+                continue;
+            }
+
             match &self.prg[cursor] {
-                Inst::InstPush(s) => {
-                    self.stack.push(s.to_string());
+                Inst::InstPush(v) => {
+                    self.stack.push(v.clone());
                 }
                 Inst::InstLoad(s) => {
-                    self.var
-                        .insert(s.to_string(), self.stack[self.stack.len() - 1].clone());
+                    if !self.stack.is_empty() {
+                        let value = self.stack[self.stack.len() - 1].clone();
+                        self.stack.pop();
+                        let name = s.clone();
+                        self.set_var(name.as_str(), value);
+                    }
                 }
-                Inst::InstGain(s) => {
-                    match self.var.get(s) {
+                Inst::InstGain(s, _) => {
+                    match self.get_var(s) {
                         Some(s) => {
-                            self.stack.push(s.to_string());
+                            self.stack.push(s);
                         }
                         None => {
-                            // error var null
+                            // the variable was set in a branch that didn't run
+                            if !self.silent {
+                                eprintln!(
+                                    "error: {} Variable `{}` has no value",
+                                    self.position(),
+                                    s
+                                );
+                            }
+                            self.stack.push(Value::Null);
                         }
                     }
                 }
-                Inst::InstPlus => {
+                Inst::InstPlus(_) => {
                     if self.stack.len() > 1 {
-                        let s = self.stack[self.stack.len() - 2].clone()
-                            + self.stack[self.stack.len() - 1].clone().as_str();
-                        self.stack.pop();
-                        self.stack.pop();
-                        self.stack.push(s);
+                        let right = self.pop();
+                        let left = self.pop();
+
+                        // two numbers are added, anything else is concatenated
+                        let res = match (&left, &right) {
+                            (Value::Number(_), Value::Number(_)) => {
+                                self.arithmetic('+', &left, &right)
+                            }
+                            _ => Value::String(left.to_string() + right.to_string().as_str()),
+                        };
+                        self.stack.push(res);
+                    }
+                }
+                Inst::InstConcat => {
+                    if self.stack.len() > 1 {
+                        let right = self.pop();
+                        let left = self.pop();
+                        let res = left.to_string() + right.to_string().as_str();
+                        self.stack.push(Value::String(res));
+                    }
+                }
+                Inst::InstMinus(_) | Inst::InstMul(_) | Inst::InstDiv(_) | Inst::InstMod(_) => {
+                    if self.stack.len() > 1 {
+                        let op = match &self.prg[cursor] {
+                            Inst::InstMinus(_) => '-',
+                            Inst::InstMul(_) => '*',
+                            Inst::InstDiv(_) => '/',
+                            _ => '%',
+                        };
+                        let right = self.pop();
+                        let left = self.pop();
+                        let res = self.arithmetic(op, &left, &right);
+                        self.stack.push(res);
+                    }
+                }
+                Inst::InstNeg(_) => {
+                    if !self.stack.is_empty() {
+                        let value = self.pop();
+                        let res = self.arithmetic('-', &Value::Number(0.into()), &value);
+                        self.stack.push(res);
                     }
                 }
                 Inst::InstPrint => {
@@ -162,199 +820,378 @@ impl Vm {
                         self.stack.pop();
                     }
                 }
-                Inst::InstReq(m, b, j, h) => {
-                    if self.stack.len() > 1 {
-                        let mut body = String::from("");
-                        let mut headers = Vec::new();
+                Inst::InstReq(m, b, j, h, _) => {
+                    let (m, b, j, h) = (m.clone(), *b, *j, *h);
+                    let (url, body, headers, expected_code) = self.pop_request(b, h);
 
-                        let expected_code = self.stack[self.stack.len() - 1].clone();
-                        self.stack.pop();
+                    match self.send(m.as_str(), url.as_str(), body, j, headers) {
+                        Some((_, code)) => {
+                            if match_code(expected_code.as_str(), code.as_str()) {
+                                self.route_ok(m, url, expected_code, code);
+                            } else {
+                                self.route_error(
+                                    m,
+                                    url,
+                                    expected_code,
+                                    Some(code),
+                                    "Invalid response code",
+                                    Vec::new(),
+                                );
+                            }
+                        }
+                        None => self.route_error(
+                            m,
+                            url,
+                            expected_code,
+                            None,
+                            "Unable to make request",
+                            Vec::new(),
+                        ),
+                    }
+                }
+                Inst::InstReqandPush(m, b, j, h, _) => {
+                    let (m, b, j, h) = (m.clone(), *b, *j, *h);
+                    let (url, body, headers, expected_code) = self.pop_request(b, h);
+
+                    match self.send(m.as_str(), url.as_str(), body, j, headers) {
+                        Some((response, code)) => {
+                            if match_code(expected_code.as_str(), code.as_str()) {
+                                self.route_ok(m, url, expected_code, code);
+                            } else {
+                                self.route_error(
+                                    m,
+                                    url,
+                                    expected_code,
+                                    Some(code),
+                                    "Invalid response code",
+                                    Vec::new(),
+                                );
+                            }
+                            self.stack.push(Value::String(response));
+                        }
+                        None => {
+                            self.route_error(
+                                m,
+                                url,
+                                expected_code,
+                                None,
+                                "Unable to make request",
+                                Vec::new(),
+                            );
+                            self.stack.push(Value::default());
+                        }
+                    }
+                }
+                Inst::InstReqandCompare(m, b, j, h, _) => {
+                    let (m, b, j, h) = (m.clone(), *b, *j, *h);
+                    let expected_content = self.pop_string();
+                    let (url, body, headers, expected_code) = self.pop_request(b, h);
 
-                        for _ in 0..*h {
-                            headers.push(self.stack[self.stack.len() - 1].clone());
-                            self.stack.pop();
+                    match self.send(m.as_str(), url.as_str(), body, j, headers) {
+                        Some((response, code)) => {
+                            if !match_code(expected_code.as_str(), code.as_str()) {
+                                self.route_error(
+                                    m,
+                                    url,
+                                    expected_code,
+                                    Some(code),
+                                    "Invalid response code",
+                                    Vec::new(),
+                                );
+                            } else if response != expected_content {
+                                self.route_error(
+                                    m,
+                                    url,
+                                    expected_code,
+                                    Some(code),
+                                    "Invalid expected response",
+                                    Vec::new(),
+                                );
+                            } else {
+                                self.route_ok(m, url, expected_code, code);
+                            }
                         }
+                        None => self.route_error(
+                            m,
+                            url,
+                            expected_code,
+                            None,
+                            "Unable to make request",
+                            Vec::new(),
+                        ),
+                    }
+                }
+                Inst::InstReqandExtract(m, b, j, h, _) => {
+                    let (m, b, j, h) = (m.clone(), *b, *j, *h);
+                    let path = self.pop_string();
+                    let (url, body, headers, expected_code) = self.pop_request(b, h);
+
+                    match self.send(m.as_str(), url.as_str(), body, j, headers) {
+                        Some((response, code)) => {
+                            let value = json::extract(response.as_str(), path.as_str())
+                                .map(|v| Value::from_json(&v));
 
-                        if *b {
-                            body = self.stack[self.stack.len() - 1].clone();
-                            self.stack.pop();
+                            if !match_code(expected_code.as_str(), code.as_str()) {
+                                self.route_error(
+                                    m,
+                                    url,
+                                    expected_code,
+                                    Some(code),
+                                    "Invalid response code",
+                                    Vec::new(),
+                                );
+                            } else if value.is_none() {
+                                self.route_error(
+                                    m,
+                                    url,
+                                    expected_code,
+                                    Some(code),
+                                    format!("Nothing found at json path `{}`", path).as_str(),
+                                    Vec::new(),
+                                );
+                            } else {
+                                self.route_ok(m, url, expected_code, code);
+                            }
+                            self.stack.push(value.unwrap_or_default());
+                        }
+                        None => {
+                            self.route_error(
+                                m,
+                                url,
+                                expected_code,
+                                None,
+                                "Unable to make request",
+                                Vec::new(),
+                            );
+                            self.stack.push(Value::default());
                         }
+                    }
+                }
+                Inst::InstReqandCompareJson(m, b, j, h, subset, _) => {
+                    let (m, b, j, h, subset) = (m.clone(), *b, *j, *h, *subset);
+                    let expected_content = self.pop_string();
+                    let (url, body, headers, expected_code) = self.pop_request(b, h);
 
-                        match Vm::req(
-                            self.stack[self.stack.len() - 1].clone(),
-                            body,
-                            m.as_str(),
-                            *j,
-                            headers,
-                        ) {
-                            Some((_, code)) => {
-                                if code == expected_code {
-                                    self.stack.pop();
-                                    self.ok += 1;
-                                } else {
-                                    if !self.silent {
-                                        eprintln!(
-                                            "route error: {} {} : Invalid response code",
-                                            m.as_str(),
-                                            self.stack[self.stack.len() - 1].clone()
-                                        );
+                    match self.send(m.as_str(), url.as_str(), body, j, headers) {
+                        Some((response, code)) => {
+                            let reason: Option<&str>;
+                            let mut diff = Vec::new();
+
+                            if !match_code(expected_code.as_str(), code.as_str()) {
+                                reason = Some("Invalid response code");
+                            } else {
+                                match (
+                                    serde_json::from_str(expected_content.as_str()),
+                                    serde_json::from_str(response.as_str()),
+                                ) {
+                                    (Err(_), _) => reason = Some("Invalid expected json"),
+                                    (_, Err(_)) => reason = Some("Response is not a valid json"),
+                                    (Ok(e), Ok(a)) => {
+                                        diff = json::diff(&e, &a, subset);
+
+                                        if diff.is_empty() {
+                                            reason = None;
+                                        } else {
+                                            reason = Some("Invalid expected response");
+                                        }
                                     }
-                                    self.stack.pop();
-                                    self.err += 1;
                                 }
                             }
-                            None => {
-                                if !self.silent {
-                                    eprintln!(
-                                        "route error: {} {} : Unable to make request",
-                                        m.as_str(),
-                                        self.stack[self.stack.len() - 1].clone()
-                                    );
+
+                            match reason {
+                                None => self.route_ok(m, url, expected_code, code),
+                                Some(r) => {
+                                    self.route_error(m, url, expected_code, Some(code), r, diff)
                                 }
-                                self.stack.pop();
-                                self.err += 1;
                             }
                         }
-                    } else {
-                        self.err += 1;
+                        None => self.route_error(
+                            m,
+                            url,
+                            expected_code,
+                            None,
+                            "Unable to make request",
+                            Vec::new(),
+                        ),
                     }
                 }
-                Inst::InstReqandPush(m, b, j, h) => {
-                    if self.stack.len() > 1 {
-                        let mut body = String::from("");
-                        let mut headers = Vec::new();
-
-                        let expected_code = self.stack[self.stack.len() - 1].clone();
-                        self.stack.pop();
+                Inst::InstHeader(_) => {
+                    if !self.stack.is_empty() {
+                        let name = self.pop_string();
 
-                        for _ in 0..*h {
-                            headers.push(self.stack[self.stack.len() - 1].clone());
-                            self.stack.pop();
-                        }
-
-                        if *b {
-                            body = self.stack[self.stack.len() - 1].clone();
-                            self.stack.pop();
+                        match self.response_header(name.as_str()) {
+                            Some(v) => self.stack.push(Value::String(v)),
+                            None => {
+                                let reason = format!("No header `{}` in the last response", name);
+                                self.header_error(name, String::new(), None, reason.as_str());
+                                self.stack.push(Value::default());
+                            }
                         }
+                    }
+                }
+                Inst::InstExpectHeader(_) => {
+                    if self.stack.len() > 1 {
+                        let expected = self.pop_string();
+                        let name = self.pop_string();
 
-                        match Vm::req(
-                            self.stack[self.stack.len() - 1].clone(),
-                            body,
-                            m.as_str(),
-                            *j,
-                            headers
-                        ) {
-                            Some((response, code)) => {
-                                if code == expected_code {
-                                    self.stack.pop();
-                                    self.ok += 1;
+                        match self.response_header(name.as_str()) {
+                            Some(v) => {
+                                if v == expected {
+                                    self.header_ok(name, expected, v);
                                 } else {
-                                    if !self.silent {
-                                        eprintln!(
-                                            "route error: {} {} : Invalid respose code",
-                                            m.as_str(),
-                                            self.stack[self.stack.len() - 1].clone()
-                                        );
-                                    }
-                                    self.stack.pop();
-                                    self.err += 1;
+                                    let reason = format!(
+                                        "Invalid header `{}`, expected `{}`, found `{}`",
+                                        name, expected, v
+                                    );
+                                    self.header_error(name, expected, Some(v), reason.as_str());
                                 }
-                                self.stack.push(response);
                             }
                             None => {
-                                if !self.silent {
-                                    eprintln!(
-                                        "route error: {} {} : Unable to make request",
-                                        m.as_str(),
-                                        self.stack[self.stack.len() - 1].clone()
-                                    );
-                                }
-                                self.stack.pop();
-                                self.err += 1;
-                                self.stack.push(String::from(""));
+                                let reason = format!("No header `{}` in the last response", name);
+                                self.header_error(name, expected, None, reason.as_str());
                             }
                         }
                     } else {
-                        self.err += 1;
+                        self.header_error(
+                            String::new(),
+                            String::new(),
+                            None,
+                            "Missing operands for the header assertion",
+                        );
                     }
                 }
-                Inst::InstReqandCompare(m, b, j, h) => {
-                    if self.stack.len() > 2 {
-                        let mut body = String::from("");
-                        let mut headers = Vec::new();
+                Inst::InstEq | Inst::InstNeq | Inst::InstContains => {
+                    if self.stack.len() > 1 {
+                        let right = self.stack[self.stack.len() - 1].clone();
+                        self.stack.pop();
 
-                        let expected_content = self.stack[self.stack.len() - 1].clone();
+                        let left = self.stack[self.stack.len() - 1].clone();
                         self.stack.pop();
 
-                        let expected_code = self.stack[self.stack.len() - 1].clone();
+                        let res = match &self.prg[cursor] {
+                            Inst::InstEq => left.equals(&right),
+                            Inst::InstNeq => !left.equals(&right),
+                            _ => left.contains(&right),
+                        };
+                        self.stack.push(Value::Bool(res));
+                    }
+                }
+                Inst::InstJump(addr) => {
+                    cursor = *addr;
+                    continue;
+                }
+                Inst::InstJumpIfNot(addr) => {
+                    if !self.stack.is_empty() {
+                        let cond = self.stack[self.stack.len() - 1].clone();
                         self.stack.pop();
 
-                        for _ in 0..*h {
-                            headers.push(self.stack[self.stack.len() - 1].clone());
-                            self.stack.pop();
+                        if !cond.truthy() {
+                            cursor = *addr;
+                            continue;
                         }
+                    }
+                }
+                Inst::InstIter(n) => {
+                    if self.stack.len() >= *n {
+                        let list = self.stack.split_off(self.stack.len() - *n);
+                        self.iters.push(Iter::List(list, 0));
+                    }
+                }
+                Inst::InstIterRange(_) => {
+                    if self.stack.len() > 1 {
+                        let high = self.stack[self.stack.len() - 1].clone();
+                        self.stack.pop();
 
-                        if *b {
-                            body = self.stack[self.stack.len() - 1].clone();
-                            self.stack.pop();
-                        }
+                        let low = self.stack[self.stack.len() - 1].clone();
+                        self.stack.pop();
 
-                        match Vm::req(
-                            self.stack[self.stack.len() - 1].clone(),
-                            body,
-                            m.as_str(),
-                            *j,
-                            headers
-                        ) {
-                            Some((response, code)) => {
-                                if code == expected_code {
-                                    if response == expected_content {
-                                        self.stack.pop();
-                                        self.ok += 1;
-                                    } else {
-                                        if !self.silent {
-                                            eprintln!(
-                                                "route error: {} {} : Invalid expected response",
-                                                m.as_str(),
-                                                self.stack[self.stack.len() - 1].clone()
-                                            );
-                                        }
-                                        self.stack.pop();
-                                        self.err += 1;
-                                    }
-                                } else {
-                                    if !self.silent {
-                                        eprintln!(
-                                            "route error: {} {} : Invalid respose code",
-                                            m.as_str(),
-                                            self.stack[self.stack.len() - 1].clone()
-                                        );
-                                    }
-                                    self.stack.pop();
-                                    self.err += 1;
+                        match (low.as_integer(), high.as_integer()) {
+                            (Some(l), Some(h)) => self.iters.push(Iter::Range(l..=h)),
+                            _ => {
+                                if !self.silent {
+                                    eprintln!(
+                                        "error: {} `{}..{}` is not a valid range",
+                                        self.position(),
+                                        low,
+                                        high
+                                    );
                                 }
+                                self.iters.push(Iter::List(Vec::new(), 0));
+                            }
+                        }
+                    }
+                }
+                Inst::InstNext(s, end) => {
+                    if let Some(iter) = self.iters.last_mut() {
+                        let value = match iter {
+                            Iter::List(list, next) => {
+                                *next += 1;
+                                list.get(*next - 1).cloned()
+                            }
+                            Iter::Range(range) => range.next().map(|i| Value::Number(i.into())),
+                        };
+
+                        match value {
+                            Some(value) => {
+                                let name = s.clone();
+                                self.set_var(name.as_str(), value);
                             }
                             None => {
+                                self.iters.pop();
+                                cursor = *end;
+                                continue;
+                            }
+                        }
+                    }
+                }
+                Inst::InstCall(addr) => {
+                    self.calls.push((cursor + 1, self.iters.len()));
+                    self.frames.push(HashMap::new());
+                    cursor = *addr;
+                    continue;
+                }
+                Inst::InstParam(s) => {
+                    if !self.stack.is_empty() {
+                        let local = self.frames.len() - 1;
+                        let value = self.stack[self.stack.len() - 1].clone();
+                        self.stack.pop();
+                        self.frames[local].insert(s.to_string(), value);
+                    }
+                }
+                Inst::InstRet => {
+                    if let Some((addr, iters)) = self.calls.pop() {
+                        // a return inside a loop leaves the loop too
+                        self.iters.truncate(iters);
+                        self.frames.pop();
+                        cursor = addr;
+                        continue;
+                    }
+                }
+                Inst::InstEnv(_) => {
+                    if !self.stack.is_empty() {
+                        let name = self.pop_string();
+
+                        match std::env::var(name.as_str()) {
+                            Ok(v) => self.stack.push(Value::String(v)),
+                            Err(_) => {
                                 if !self.silent {
                                     eprintln!(
-                                        "route error: {} {} : Unable to make request",
-                                        m.as_str(),
-                                        self.stack[self.stack.len() - 1].clone()
+                                        "warning: {} Environment variable `{}` is not set",
+                                        self.position(),
+                                        name
                                     );
                                 }
-                                self.stack.pop();
-                                self.err += 1;
+                                self.stack.push(Value::default());
                             }
                         }
-                    } else {
-                        self.err += 1;
                     }
                 }
             }
             cursor += 1;
 
+            // stop at the first failed route, the caller still reports what was made
             if self.blocking && self.err != 0 {
-                exit(1);
+                return Some((self.ok, self.err));
             }
         }
 